@@ -1,25 +1,83 @@
-use calibright::{CalibrightBuilder, CalibrightError};
+use calibright::{CalibrightBuilder, CalibrightError, ChangeEvent, OverflowPolicy, WriteMethod};
 
 use clap::{ArgGroup, Parser};
 
+use std::io::Write;
+
+#[cfg(feature = "notify-send")]
+mod notification;
+
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 #[command(group(
             ArgGroup::new("action")
                 .required(true)
-                .args(["get", "set", "inc", "dec"]),
+                .args(["list", "get", "set", "inc", "dec", "watch", "dump_config", "check"]),
         ))]
 struct Args {
-    /// Regular expression for the devices to match
-    #[arg(long, value_name = "regex", default_value_t = String::from("."))]
-    device: String,
+    /// Regular expression for the devices to match, by device or connector (e.g. DP-1) name
+    /// [default: $CALIBRIGHT_DEVICE or .]
+    #[arg(long, value_name = "regex")]
+    device: Option<String>,
+
+    /// List the matched devices, along with their configured alias.
+    #[arg(long)]
+    list: bool,
+
+    /// Print the resolved config of each matched device, after merging the global config and
+    /// the device overrides.
+    #[arg(long)]
+    dump_config: bool,
+
+    /// Check that the files each matched device is read from and written to exist and can be
+    /// accessed, e.g. after changing `write_file` or `max_file` in the config.
+    #[arg(long)]
+    check: bool,
 
     /// Print out the current backlight brightness of each output with such a control.
     /// The brightness is represented as a percentage of the maximum brightness supported.
     #[arg(long)]
     get: bool,
 
+    /// Print the brightness every time it changes, until interrupted.
+    #[arg(long)]
+    watch: bool,
+
+    /// Print one JSON object per line with --watch, e.g. for status bars.
+    #[arg(long)]
+    json: bool,
+
+    /// Mark the brightness printed by --get as the perceived level with a `%p` suffix.
+    /// The level follows the `root_scaling` and `gamma` curve of the config, so configure those
+    /// to match the perceived brightness of the devices.
+    #[arg(long)]
+    perceptual: bool,
+
+    /// Number of decimal places to print with --get, --set and --watch.
+    #[arg(long, value_name = "n", default_value_t = 0)]
+    precision: usize,
+
+    /// Show a desktop notification with the resulting brightness after --set, --inc or --dec.
+    /// Nothing is shown if there is no notification service.
+    #[cfg(feature = "notify-send")]
+    #[arg(long)]
+    notify: bool,
+
+    /// Fail instead of clamping when the resulting brightness is outside of 0 to 100.
+    #[arg(long)]
+    strict: bool,
+
+    /// Print the raw values that would be written instead of changing the brightness.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print how each device was written to (logind or sysfs) after changing the brightness,
+    /// which hardware brightness a `--set` value maps to, and the logind session and read retries
+    /// with `--list`.
+    #[arg(long)]
+    verbose: bool,
+
     /// Sets each backlight brightness to the specified level.
     #[arg(long, value_name = "percent")]
     set: Option<f64>,
@@ -34,27 +92,180 @@ struct Args {
 }
 
 #[tokio::main]
-async fn main() -> Result<(), CalibrightError> {
+async fn main() {
     env_logger::init();
     let args = Args::parse();
 
-    let mut calibright = CalibrightBuilder::new()
-        .with_device_regex(args.device.as_str())
+    if let Err(e) = run(args).await {
+        eprintln!("Error: {}", e.full_chain());
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> Result<(), CalibrightError> {
+    let mut builder = CalibrightBuilder::new();
+    if let Some(device) = &args.device {
+        builder = builder.with_device_regex(device);
+    }
+    let mut calibright = builder
+        .with_dry_run(args.dry_run)
+        .with_read_only(args.get || args.watch)
+        .with_overflow_policy(if args.strict {
+            OverflowPolicy::Error
+        } else {
+            OverflowPolicy::Clamp
+        })
         .build()
         .await?;
 
     if let Some(set) = args.set {
         calibright.set_brightness(set / 100.0).await?;
-    } else {
+        // Rounding to the raw steps of a device can land on a different value
+        let effective = calibright.effective_brightness(set / 100.0)? * 100.0;
+        if format!("{:.*}", args.precision, effective) != format!("{:.*}", args.precision, set) {
+            println!(
+                "set to {:.*}% (requested {:.*}%)",
+                args.precision, effective, args.precision, set
+            );
+        }
+        if args.verbose {
+            let hardware_brightnesses = calibright.hardware_brightness_per_device(set / 100.0)?;
+            for (device_name, hardware_brightness) in hardware_brightnesses {
+                let note = match calibright.device_calibration_window(&device_name) {
+                    Some([low, _]) if set <= 0.0 && low > 0.0 => " (min calibration)",
+                    Some([_, high]) if set >= 100.0 && high < 1.0 => " (max calibration)",
+                    _ => "",
+                };
+                println!(
+                    "{}: {:.*}% requested maps to {:.*}% hardware{note}",
+                    device_name.to_string_lossy(),
+                    args.precision,
+                    set,
+                    args.precision,
+                    hardware_brightness * 100.0
+                );
+            }
+        }
+    } else if let Some(inc) = args.inc {
+        calibright.adjust_brightness(inc / 100.0).await?;
+    } else if let Some(dec) = args.dec {
+        calibright.adjust_brightness(-dec / 100.0).await?;
+    } else if args.list {
+        for device_name in calibright.matched_device_names() {
+            let connector = calibright
+                .device_connector(device_name)
+                .flatten()
+                .map(|connector| format!(" [{connector}]"))
+                .unwrap_or_default();
+            match calibright.device_alias(device_name) {
+                Some(alias) => println!("{}{connector} ({alias})", device_name.to_string_lossy()),
+                None => println!("{}{connector}", device_name.to_string_lossy()),
+            }
+            if args.verbose {
+                match calibright.device_session_path(device_name).flatten() {
+                    Some(session_path) => println!("  logind session: {session_path}"),
+                    None => println!("  logind session: none, writing to sysfs"),
+                }
+                if let Some(retries) = calibright.device_last_read_retries(device_name) {
+                    println!("  retries during the last read: {retries}");
+                }
+            }
+        }
+        for override_name in calibright.unused_overrides() {
+            eprintln!("Warning: config override [{override_name}] matches no device");
+        }
+    } else if args.dump_config {
+        let mut configs: Vec<_> = calibright.effective_config().into_iter().collect();
+        configs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (device_name, config) in configs {
+            println!("{}: {config:#?}", device_name.to_string_lossy());
+        }
+    } else if args.check {
+        if let Err(problems) = calibright.validate_devices().await {
+            for (device_name, problem) in &problems {
+                eprintln!(
+                    "{}: {}",
+                    device_name.to_string_lossy(),
+                    problem.full_chain()
+                );
+            }
+            return Err(CalibrightError::Other(format!(
+                "{} of {} devices have problems",
+                problems.len(),
+                calibright.matched_device_names().len()
+            )));
+        }
+        println!("All devices are ok");
+    } else if args.watch {
+        loop {
+            let event = calibright.next().await?;
+            let brightness = calibright.get_brightness().await? * 100.0;
+            if args.json {
+                let kind = match event {
+                    ChangeEvent::DeviceAdded { .. } => "device_added",
+                    ChangeEvent::DeviceRemoved { .. } => "device_removed",
+                    _ => "brightness_changed",
+                };
+                println!(
+                    r#"{{"event":"{kind}","device":{},"brightness":{:.*}}}"#,
+                    json_string(&event.device().to_string_lossy()),
+                    args.precision,
+                    brightness
+                );
+            } else {
+                println!("{:.*}", args.precision, brightness);
+            }
+            std::io::stdout().flush()?;
+        }
+    } else if args.get {
         let brightness = calibright.get_brightness().await?;
-        if args.get {
-            println!("{:?}", (brightness * 100.0).round());
-        } else if let Some(inc) = args.inc {
-            calibright.set_brightness(brightness + inc / 100.0).await?;
-        } else if let Some(dec) = args.dec {
-            calibright.set_brightness(brightness - dec / 100.0).await?;
+        let suffix = if args.perceptual { "%p" } else { "" };
+        println!("{:.*}{suffix}", args.precision, brightness * 100.0);
+    }
+
+    if args.verbose && !args.dry_run && !args.get && !args.list && !args.dump_config && !args.check
+    {
+        for device_name in calibright.matched_device_names() {
+            let method = match calibright.last_write_method(device_name) {
+                Some(WriteMethod::Logind) => "via logind",
+                Some(WriteMethod::Sysfs) => "via sysfs",
+                Some(_) => "via the privileged helper",
+                None => "not written",
+            };
+            println!("{}: {method}", device_name.to_string_lossy());
+        }
+    }
+
+    #[cfg(feature = "notify-send")]
+    if args.notify && (args.set.is_some() || args.inc.is_some() || args.dec.is_some()) {
+        // Best-effort, the brightness was already changed
+        match calibright.get_brightness().await {
+            Ok(brightness) => notification::show_brightness(brightness * 100.0).await,
+            Err(e) => log::debug!("not showing a notification: {e}"),
+        }
+    }
+
+    if args.dry_run && !args.get && !args.list && !args.dump_config && !args.check {
+        for (device_name, raw_brightness) in calibright.cached_raw_brightness() {
+            println!("{}: {raw_brightness}", device_name.to_string_lossy());
         }
     }
 
     Ok(())
 }
+
+/// `s` as a JSON string literal, with quotes.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use zbus::zvariant::Value;
+use zbus::Connection;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// Show the brightness (0 to 100) as a desktop notification. Errors, e.g. because there is no
+/// session bus or notification service, are only logged.
+pub async fn show_brightness(brightness: f64) {
+    let res: zbus::Result<u32> = async {
+        let connection = Connection::session().await?;
+        let proxy = NotificationsProxy::new(&connection).await?;
+        let hints = HashMap::from([
+            // Progress bar, supported by most notification services
+            ("value", Value::from(brightness.round() as i32)),
+            // Replace the previous brightness notification instead of stacking them
+            ("x-canonical-private-synchronous", Value::from("calibright")),
+            ("x-dunst-stack-tag", Value::from("calibright")),
+        ]);
+        proxy
+            .notify(
+                "calibright",
+                0,
+                "display-brightness-symbolic",
+                "Brightness",
+                &format!("{brightness:.0}%"),
+                &[],
+                hints,
+                -1,
+            )
+            .await
+    }
+    .await;
+    if let Err(e) = res {
+        log::debug!("failed to show a notification: {e}");
+    }
+}
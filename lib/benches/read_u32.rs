@@ -0,0 +1,130 @@
+//! Reading the brightness of many monitors from real files, comparing the fixed-buffer integer
+//! reader used by `get_brightness` (`read_u32`) with reading each file into a trimmed `String`
+//! first.
+//!
+//! Run with `cargo bench -p calibright --bench read_u32`.
+
+use std::ffi::OsString;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use futures_util::future::join_all;
+
+use calibright::{
+    CalibrightBuilder, CalibrightConfig, DeviceConfig, FsFuture, FsProvider, TokioFs,
+};
+
+const DEVICES_PATH: &str = "/sys/class/backlight";
+const MONITORS: usize = 16;
+const ITERATIONS: u32 = 2000;
+
+/// The real file system, with the backlight devices moved to a temporary directory.
+struct RelocatedFs(PathBuf);
+
+impl RelocatedFs {
+    fn relocate(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(DEVICES_PATH) {
+            Ok(rest) => self.0.join(rest),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+}
+
+impl FsProvider for RelocatedFs {
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>> {
+        let path = self.relocate(path);
+        Box::pin(async move { TokioFs.read(&path).await })
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()> {
+        let path = self.relocate(path);
+        Box::pin(async move { TokioFs.write(&path, contents).await })
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>> {
+        let path = self.relocate(path);
+        Box::pin(async move { TokioFs.read_dir(&path).await })
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        let path = self.relocate(path);
+        Box::pin(async move { TokioFs.exists(&path).await })
+    }
+
+    fn can_write<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        let path = self.relocate(path);
+        Box::pin(async move { TokioFs.can_write(&path).await })
+    }
+
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> FsFuture<'a, PathBuf> {
+        let path = self.relocate(path);
+        Box::pin(async move { TokioFs.canonicalize(&path).await })
+    }
+
+    fn can_watch(&self) -> bool {
+        false
+    }
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{name:<24} {:>10.2?} per call ({MONITORS} monitors)",
+        elapsed / ITERATIONS
+    );
+}
+
+fn main() {
+    let root = std::env::temp_dir().join(format!("calibright-bench-{}", std::process::id()));
+    let mut read_files = Vec::new();
+    for i in 0..MONITORS {
+        let device_path = root.join(format!("ddcci{i}"));
+        std::fs::create_dir_all(&device_path).unwrap();
+        std::fs::write(device_path.join("max_brightness"), "100\n").unwrap();
+        std::fs::write(device_path.join("brightness"), "50\n").unwrap();
+        std::fs::write(device_path.join("actual_brightness"), "50\n").unwrap();
+        read_files.push(device_path.join("actual_brightness"));
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let config = CalibrightConfig::from_deserializer(
+            toml::Deserializer::new(""),
+            &DeviceConfig::default(),
+        )
+        .unwrap();
+        let calibright = CalibrightBuilder::new()
+            .with_config(config)
+            .with_fs_provider(RelocatedFs(root.clone()))
+            .with_read_only(true)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(calibright.matched_device_names().len(), MONITORS);
+
+        // Reads every device with `read_u32`, without the calibration math of `get_brightness`
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(calibright.peek_raw_brightness().await.unwrap());
+        }
+        report("peek_raw_brightness", start.elapsed());
+
+        // The same reads as they were done before `read_u32`
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(
+                join_all(read_files.iter().map(|read_file| async move {
+                    let content = tokio::fs::read_to_string(read_file).await.unwrap();
+                    content.trim_end().parse::<u32>().unwrap()
+                }))
+                .await,
+            );
+        }
+        report("read_to_string + parse", start.elapsed());
+    });
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
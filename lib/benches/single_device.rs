@@ -0,0 +1,61 @@
+//! Reading the brightness of a single backlight, comparing the single device fast path of
+//! `get_brightness` with `get_subsystem_brightness`, which always joins the device futures.
+//!
+//! Run with `cargo bench -p calibright --bench single_device`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use calibright::{CalibrightBuilder, CalibrightConfig, DeviceConfig, MemoryFs, Subsystem};
+
+const DEVICE_PATH: &str = "/sys/class/backlight/intel_backlight";
+const ITERATIONS: u32 = 200_000;
+
+fn report(name: &str, elapsed: Duration) {
+    println!("{name:<24} {:>10.2?} per call", elapsed / ITERATIONS);
+}
+
+fn main() {
+    // In memory, so that the file system doesn't drown out the difference
+    let fs = MemoryFs::new();
+    fs.insert(format!("{DEVICE_PATH}/max_brightness"), "100\n");
+    fs.insert(format!("{DEVICE_PATH}/actual_brightness"), "50\n");
+    fs.insert(format!("{DEVICE_PATH}/brightness"), "50\n");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let config = CalibrightConfig::from_deserializer(
+            toml::Deserializer::new(""),
+            &DeviceConfig::default(),
+        )
+        .unwrap();
+        let mut calibright = CalibrightBuilder::new()
+            .with_config(config)
+            .with_fs_provider(fs)
+            .with_read_only(true)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(calibright.matched_device_names(), ["intel_backlight"]);
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(calibright.get_brightness().await.unwrap());
+        }
+        report("get_brightness", start.elapsed());
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(
+                calibright
+                    .get_subsystem_brightness(Subsystem::Backlight)
+                    .await
+                    .unwrap(),
+            );
+        }
+        report("get_subsystem_brightness", start.elapsed());
+    });
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::consts::*;
+use crate::errors::*;
+use crate::util::weighted_mean;
+
+/// A brightness value in [`BRIGHTNESS_RANGE`] (0.0 to 1.0 inclusive).
+///
+/// The library works with values from 0.0 to 1.0, while users (e.g. the CLI) usually think in
+/// percent from 0 to 100. This type makes the unit explicit.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Brightness(f64);
+
+impl Brightness {
+    /// Create a brightness from a value in range 0.0 to 1.0 (inclusive).
+    pub fn new(value: f64) -> Result<Self> {
+        if BRIGHTNESS_RANGE.contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(CalibrightError::InvalidBrightness(value))
+        }
+    }
+
+    /// Create a brightness from a percentage in range 0.0 to 100.0 (inclusive).
+    pub fn from_percent(percent: f64) -> Result<Self> {
+        Self::new(percent / 100.0)
+    }
+
+    /// The brightness in range 0.0 to 1.0 (inclusive).
+    pub fn value(self) -> f64 {
+        self.0
+    }
+
+    /// The brightness as a percentage in range 0.0 to 100.0 (inclusive).
+    pub fn as_percent(self) -> f64 {
+        self.0 * 100.0
+    }
+}
+
+/// What to do with brightness values outside of [`BRIGHTNESS_RANGE`] when setting or adjusting
+/// the brightness. NaN is always rejected with [`CalibrightError::InvalidBrightness`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Clamp the brightness to the valid range.
+    #[default]
+    Clamp,
+    /// Return [`CalibrightError::InvalidBrightness`].
+    Error,
+}
+
+impl OverflowPolicy {
+    pub(crate) fn apply(self, value: f64) -> Result<f64> {
+        if value.is_nan() {
+            return Err(CalibrightError::InvalidBrightness(value));
+        }
+        match self {
+            OverflowPolicy::Clamp => Ok(value.clamp(0.0, 1.0)),
+            OverflowPolicy::Error => Brightness::new(value).map(Brightness::value),
+        }
+    }
+}
+
+/// What [`crate::Calibright::get_brightness`] does with a device that can't be read, e.g. while a
+/// DDC/CI monitor is busy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadErrorPolicy {
+    /// Leave the device out of the reported brightness.
+    #[default]
+    Skip,
+    /// Use the last brightness read from or written to the device.
+    LastKnown,
+    /// Count the device as having a brightness of `0.0`.
+    Zero,
+}
+
+/// How the brightness of multiple devices is combined into the single value reported by
+/// [`crate::Calibright::get_brightness`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// The average brightness, weighted by each device's `weight`.
+    #[default]
+    Mean,
+    /// The brightness of the brightest device.
+    Max,
+    /// The brightness of the dimmest device.
+    Min,
+}
+
+impl Aggregation {
+    /// The names of all variants, as accepted by [`str::parse`], e.g. to list them in a UI.
+    pub const VARIANTS: &'static [&'static str] = &["mean", "max", "min"];
+
+    /// The name of this variant, see [`Aggregation::VARIANTS`].
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Aggregation::Mean => "mean",
+            Aggregation::Max => "max",
+            Aggregation::Min => "min",
+        }
+    }
+
+    /// Combine `(brightness, weight)` pairs. Devices with a weight of `0.0` are ignored.
+    pub(crate) fn aggregate(self, values: &[(f64, f64)]) -> Result<f64> {
+        let included = values
+            .iter()
+            .filter(|(_, weight)| *weight > 0.0)
+            .map(|(value, _)| *value);
+        match self {
+            Aggregation::Mean => weighted_mean(values),
+            Aggregation::Max => included.reduce(f64::max).ok_or(CalibrightError::NoDevices),
+            Aggregation::Min => included.reduce(f64::min).ok_or(CalibrightError::NoDevices),
+        }
+    }
+}
+
+/// How [`crate::Calibright::set_brightness`] applies a brightness to multiple devices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SetAggregation {
+    /// Set every device to the given brightness.
+    #[default]
+    Uniform,
+    /// Adjust every device by the same amount, so that the reported brightness (see
+    /// [`Aggregation`]) becomes the given brightness while preserving the differences between
+    /// devices.
+    Preserve,
+}
+
+impl SetAggregation {
+    /// The names of all variants, as accepted by [`str::parse`], e.g. to list them in a UI.
+    pub const VARIANTS: &'static [&'static str] = &["uniform", "preserve"];
+
+    /// The name of this variant, see [`SetAggregation::VARIANTS`].
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SetAggregation::Uniform => "uniform",
+            SetAggregation::Preserve => "preserve",
+        }
+    }
+}
+
+impl FromStr for Aggregation {
+    type Err = CalibrightError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mean" => Ok(Aggregation::Mean),
+            "max" => Ok(Aggregation::Max),
+            "min" => Ok(Aggregation::Min),
+            _ => Err(invalid_variant("aggregation", s, Aggregation::VARIANTS)),
+        }
+    }
+}
+
+impl FromStr for SetAggregation {
+    type Err = CalibrightError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "uniform" => Ok(SetAggregation::Uniform),
+            "preserve" => Ok(SetAggregation::Preserve),
+            _ => Err(invalid_variant(
+                "set aggregation",
+                s,
+                SetAggregation::VARIANTS,
+            )),
+        }
+    }
+}
+
+fn invalid_variant(kind: &str, value: &str, variants: &[&str]) -> CalibrightError {
+    CalibrightError::Other(format!(
+        "Invalid {kind} {value:?}, expected one of {}",
+        variants.join(", ")
+    ))
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for SetAggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<f64> for Brightness {
+    type Error = CalibrightError;
+
+    fn try_from(value: f64) -> Result<Self> {
+        Self::new(value)
+    }
+}
+
+impl From<Brightness> for f64 {
+    fn from(brightness: Brightness) -> Self {
+        brightness.0
+    }
+}
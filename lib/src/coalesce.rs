@@ -0,0 +1,49 @@
+use crate::device::Device;
+
+use futures_util::future::join_all;
+use tokio::sync::watch;
+
+make_log_macro!(debug, "calibright_coalesce");
+
+/// Applies the brightness set for a set of devices on a separate task. Only the latest target
+/// is applied: targets set while a write is in progress replace each other, so that slow (e.g.
+/// DDC) devices don't fall behind, but the last one is always written.
+///
+/// The devices are clones sharing their cached brightness with the devices of the
+/// [`crate::Calibright`], so the writes show up there directly.
+///
+/// The task stops once this is dropped and the pending target, if any, has been written.
+pub(crate) struct CoalescedWriter {
+    target: watch::Sender<Option<f64>>,
+}
+
+impl CoalescedWriter {
+    pub fn spawn(devices: Vec<Device>) -> Self {
+        let (target, rx) = watch::channel(None);
+        tokio::spawn(write_latest(devices, rx));
+
+        Self { target }
+    }
+
+    /// Replace the target brightness, without waiting for it to be written.
+    pub fn set(&self, brightness: f64) {
+        self.target.send_replace(Some(brightness));
+    }
+}
+
+async fn write_latest(mut devices: Vec<Device>, mut target: watch::Receiver<Option<f64>>) {
+    // A target replaced before it was seen is still reported as changed after the sender is
+    // dropped, so the latest target is always written.
+    while target.changed().await.is_ok() {
+        let Some(brightness) = *target.borrow_and_update() else {
+            continue;
+        };
+        debug!("writing coalesced brightness {brightness}");
+        join_all(devices.iter_mut().map(|device| async move {
+            if let Err(e) = device.set_brightness(brightness).await {
+                debug!("{e}");
+            }
+        }))
+        .await;
+    }
+}
@@ -3,7 +3,8 @@ use crate::errors::*;
 use crate::util::*;
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Component, Path, PathBuf};
 
 use dirs::config_dir;
 use serde::Deserialize;
@@ -18,32 +19,306 @@ struct UnresolvedDeviceConfig {
     #[serde(default, deserialize_with = "deserialize_root_scaling")]
     root_scaling: Option<f64>,
 
+    #[serde(default, deserialize_with = "deserialize_ddcci_sleep_multiplier")]
     ddcci_sleep_multiplier: Option<f64>,
 
+    #[serde(default, deserialize_with = "deserialize_ddcci_max_tries_write_read")]
     ddcci_max_tries_write_read: Option<u8>,
 
     #[serde(default, deserialize_with = "deserialize_calibration")]
     calibration: Option<[f64; 2]>,
+
+    #[serde(default, deserialize_with = "deserialize_gamma")]
+    gamma: Option<f64>,
+
+    #[serde(default, deserialize_with = "deserialize_offset")]
+    offset: Option<f64>,
+
+    read_hysteresis: Option<u32>,
+
+    #[serde(default, deserialize_with = "deserialize_weight")]
+    weight: Option<f64>,
+
+    alias: Option<String>,
+
+    invert: Option<bool>,
+
+    #[serde(default, deserialize_with = "deserialize_device_file")]
+    write_file: Option<PathBuf>,
+
+    #[serde(default, deserialize_with = "deserialize_device_file")]
+    max_file: Option<PathBuf>,
+
+    #[serde(default, deserialize_with = "deserialize_ddcci_read_base_ms")]
+    ddcci_read_base_ms: Option<u64>,
+
+    #[serde(default, deserialize_with = "deserialize_max_nits")]
+    max_nits: Option<f64>,
+
+    #[serde(default, deserialize_with = "deserialize_curve_points")]
+    curve_points: Option<Vec<[f64; 2]>>,
+
+    sysfs_fallback: Option<bool>,
+
+    #[serde(default, deserialize_with = "deserialize_max_brightness_cap")]
+    max_brightness_cap: Option<f64>,
+
+    #[serde(default, deserialize_with = "deserialize_calibration_snap")]
+    calibration_snap: Option<f64>,
+}
+
+/// Check that the config value `name` is a finite number in `range`, or describe why it isn't.
+/// Used by both the deserializers and [`DeviceConfig::validate`].
+fn check_finite_in_range(
+    name: &str,
+    value: f64,
+    range: impl RangeBounds<f64>,
+) -> std::result::Result<(), String> {
+    if !value.is_finite() {
+        return Err(format!(
+            "Invalid {name} {value}, NaN and infinity are not allowed"
+        ));
+    }
+    if !range.contains(&value) {
+        let expected = match (range.start_bound(), range.end_bound()) {
+            (Bound::Included(start), Bound::Unbounded) => format!("of at least {start:?}"),
+            (Bound::Excluded(start), Bound::Unbounded) => format!("greater than {start:?}"),
+            (Bound::Included(start), Bound::Excluded(end)) => {
+                format!("in the range of {start:?} to {end:?} (exclusive)")
+            }
+            (Bound::Included(start), Bound::Included(end)) => {
+                format!("in the range of {start:?} to {end:?}")
+            }
+            (start, end) => format!("in the range of {start:?} to {end:?}"),
+        };
+        return Err(format!(
+            "Invalid {name} {value}, expected a number {expected}"
+        ));
+    }
+    Ok(())
+}
+
+/// Deserialize an optional number that is checked with [`check_finite_in_range`].
+fn deserialize_finite_in_range<'de, D>(
+    deserializer: D,
+    name: &str,
+    range: impl RangeBounds<f64>,
+) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<f64>::deserialize(deserializer)?;
+
+    if let Some(value) = value {
+        debug!("{name} {:?}", value);
+        check_finite_in_range(name, value, range).map_err(serde::de::Error::custom)?;
+    }
+
+    Ok(value)
 }
 
 fn deserialize_root_scaling<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let root_scaling = Option::<f64>::deserialize(deserializer)?;
+    deserialize_finite_in_range(deserializer, "root_scaling", ROOT_SCALDING_RANGE)
+}
 
-    if let Some(root_scaling) = root_scaling {
-        debug!("{:?}", root_scaling);
+fn deserialize_ddcci_max_tries_write_read<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let max_tries = Option::<u8>::deserialize(deserializer)?;
+
+    if max_tries == Some(0) {
+        return Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Unsigned(0),
+            &"number of attempts of at least 1",
+        ));
+    }
 
-        if !ROOT_SCALDING_RANGE.contains(&root_scaling) {
+    Ok(max_tries)
+}
+
+fn deserialize_ddcci_read_base_ms<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let base_ms = Option::<u64>::deserialize(deserializer)?;
+
+    if let Some(base_ms) = base_ms {
+        if !DDCCI_READ_BASE_MS_RANGE.contains(&base_ms) {
             return Err(serde::de::Error::invalid_value(
-                serde::de::Unexpected::Float(root_scaling),
-                &"number in the range of 0.1 to 10.",
+                serde::de::Unexpected::Unsigned(base_ms),
+                &"number of milliseconds in the range of 1 to 1000",
+            ));
+        }
+    }
+
+    Ok(base_ms)
+}
+
+fn deserialize_gamma<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_finite_in_range(deserializer, "gamma", GAMMA_RANGE)
+}
+
+fn deserialize_offset<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let offset = deserialize_finite_in_range(deserializer, "offset", OFFSET_RANGE)?;
+    Ok(offset.map(|offset| offset / 100.0))
+}
+
+fn deserialize_weight<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_finite_in_range(deserializer, "weight", WEIGHT_RANGE)
+}
+
+fn deserialize_max_brightness_cap<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let max_brightness_cap =
+        deserialize_finite_in_range(deserializer, "max_brightness_cap", MAX_BRIGHTNESS_CAP_RANGE)?;
+    Ok(max_brightness_cap.map(|max_brightness_cap| max_brightness_cap / 100.0))
+}
+
+fn deserialize_calibration_snap<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let calibration_snap =
+        deserialize_finite_in_range(deserializer, "calibration_snap", CALIBRATION_SNAP_RANGE)?;
+    Ok(calibration_snap.map(|calibration_snap| calibration_snap / 100.0))
+}
+
+fn deserialize_ddcci_sleep_multiplier<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_finite_in_range(
+        deserializer,
+        "ddcci_sleep_multiplier",
+        DDCCI_SLEEP_MULTIPLIER_RANGE,
+    )
+}
+
+fn deserialize_max_nits<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_finite_in_range(deserializer, "max_nits", MAX_NITS_RANGE)
+}
+
+fn deserialize_profiles<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, HashMap<String, f64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut profiles = HashMap::<String, HashMap<String, f64>>::deserialize(deserializer)?;
+    for (profile, targets) in profiles.iter_mut() {
+        for (device, target) in targets.iter_mut() {
+            check_finite_in_range(&format!("{profile}.{device}"), *target, CALIBRATION_RANGE)
+                .map_err(serde::de::Error::custom)?;
+            *target /= 100.0;
+        }
+    }
+
+    Ok(profiles)
+}
+
+/// A `[[schedule]]` entry: config values applied to all devices from `start` until `end`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct ScheduleEntry {
+    /// Minutes since midnight.
+    #[serde(deserialize_with = "deserialize_time_of_day")]
+    start: u32,
+    /// Minutes since midnight, before `start` if the window spans midnight.
+    #[serde(deserialize_with = "deserialize_time_of_day")]
+    end: u32,
+    config: UnresolvedDeviceConfig,
+}
+
+impl ScheduleEntry {
+    /// Whether the window contains the given minute of the day.
+    fn contains(&self, minute: u32) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Parse a `"HH:MM"` time into minutes since midnight.
+fn deserialize_time_of_day<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time = String::deserialize(deserializer)?;
+    time.split_once(':')
+        .and_then(|(hour, minute)| {
+            let hour = hour.parse::<u32>().ok().filter(|hour| *hour < 24)?;
+            let minute = minute.parse::<u32>().ok().filter(|minute| *minute < 60)?;
+            Some(hour * 60 + minute)
+        })
+        .ok_or_else(|| {
+            serde::de::Error::invalid_value(serde::de::Unexpected::Str(&time), &"time as \"HH:MM\"")
+        })
+}
+
+fn deserialize_schedule<'de, D>(deserializer: D) -> Result<Vec<ScheduleEntry>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let schedule = Vec::<ScheduleEntry>::deserialize(deserializer)?;
+    let mut covered = vec![false; MINUTES_PER_DAY as usize];
+    for entry in &schedule {
+        if entry.start == entry.end {
+            return Err(serde::de::Error::custom(
+                "schedule window must not be empty (start equals end)",
             ));
         }
+        for minute in 0..MINUTES_PER_DAY {
+            if entry.contains(minute) {
+                if covered[minute as usize] {
+                    return Err(serde::de::Error::custom(format!(
+                        "schedule windows overlap at {:02}:{:02}",
+                        minute / 60,
+                        minute % 60
+                    )));
+                }
+                covered[minute as usize] = true;
+            }
+        }
+    }
+
+    Ok(schedule)
+}
+
+fn deserialize_curve_points<'de, D>(deserializer: D) -> Result<Option<Vec<[f64; 2]>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let curve_points = Option::<Vec<[f64; 2]>>::deserialize(deserializer)?;
+    if let Some(curve_points) = &curve_points {
+        check_curve_points(curve_points).map_err(|e| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Other(format!("{curve_points:?}").as_str()),
+                &e.as_str(),
+            )
+        })?;
     }
 
-    Ok(root_scaling)
+    Ok(curve_points)
 }
 
 fn deserialize_calibration<'de, D>(deserializer: D) -> Result<Option<[f64; 2]>, D::Error>
@@ -53,6 +328,11 @@ where
     let calibration = Option::<[f64; 2]>::deserialize(deserializer)?;
     if let Some(calibration) = calibration {
         debug!("{:?}", calibration);
+        for val in calibration {
+            check_finite_in_range("calibration", val, CALIBRATION_RANGE)
+                .map_err(serde::de::Error::custom)?;
+        }
+
         if calibration[0] > calibration[1] {
             return Err(serde::de::Error::invalid_value(
                 serde::de::Unexpected::Other(format!("{calibration:?}").as_str()),
@@ -63,19 +343,39 @@ where
                 .as_str(),
             ));
         }
+    }
+    Ok(calibration.map(|limits| limits.map(|val| val / 100.0)))
+}
 
-        for val in calibration {
-            if !CALIBRATION_RANGE.contains(&val) {
-                return Err(serde::de::Error::invalid_value(
-                    serde::de::Unexpected::Float(val),
-                    &"number in the range of 0.0 to 100.0",
-                ));
-            }
+fn deserialize_device_file<'de, D>(deserializer: D) -> Result<Option<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let device_file = Option::<PathBuf>::deserialize(deserializer)?;
+
+    if let Some(device_file) = &device_file {
+        debug!("{:?}", device_file);
+
+        // Don't allow accessing files outside of the device directory
+        if device_file.as_os_str().is_empty()
+            || !device_file
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(serde::de::Error::invalid_value(
+                serde::de::Unexpected::Str(&device_file.to_string_lossy()),
+                &"path relative to the device directory, without `..`",
+            ));
         }
     }
-    Ok(calibration.map(|limits| limits.map(|val| val / 100.0)))
+
+    Ok(device_file)
 }
 
+/// The resolved configuration of a device.
+///
+/// The fields are public for convenience, but values set directly are not validated. Use
+/// [`DeviceConfig::validated`] to apply the same checks as the config file.
 #[derive(Clone, Debug, SmartDefault)]
 pub struct DeviceConfig {
     /// Scaling exponent reciprocal (ie. root).
@@ -87,6 +387,7 @@ pub struct DeviceConfig {
     pub ddcci_sleep_multiplier: f64,
 
     /// The maximum number of times to attempt writing to  or reading from a ddcci monitor.
+    /// This is the total number of attempts, so `1` means no retries.
     #[default(10)]
     pub ddcci_max_tries_write_read: u8,
 
@@ -94,12 +395,189 @@ pub struct DeviceConfig {
     /// Calibration values are given as 0-100 in the config, but mapped to 0-1.
     #[default([0.0, 1.0])]
     pub calibration: [f64; 2],
+
+    /// Whether `calibration` was set in the config (or with
+    /// [`crate::CalibrightBuilder::with_default_calibration`]), even if to the default. If not,
+    /// the lower bound is raised to the `brightness_min` reported by the driver, if any.
+    pub calibration_set: bool,
+
+    /// Scaling exponent applied together with `root_scaling`.
+    #[default(1.0)]
+    pub gamma: f64,
+
+    /// The portion of the hardware range below which the device is unusable.
+    /// The offset is given as 0-100 in the config, but mapped to 0-1.
+    #[default(0.0)]
+    pub offset: f64,
+
+    /// Raw brightness changes smaller than this are ignored when reading, to stabilize jittery
+    /// ddcci reads.
+    #[default(0)]
+    pub read_hysteresis: u32,
+
+    /// Weight of this device in the average brightness. A weight of `0.0` excludes the device
+    /// from the average.
+    #[default(1.0)]
+    pub weight: f64,
+
+    /// A human readable name for the device, e.g. `"Living Room TV"` for `ddcci7`.
+    /// Only applies to device sections, it is ignored in the `[global]` section.
+    pub alias: Option<String>,
+
+    /// Whether the device reports and accepts brightness inverted, i.e. `0` is the brightest.
+    pub invert: bool,
+
+    /// The file the brightness is written to, relative to the device directory in sysfs.
+    /// Defaults to `brightness`.
+    pub write_file: Option<PathBuf>,
+
+    /// The file the max brightness is read from, relative to the device directory in sysfs.
+    /// Defaults to `max_brightness`, or one of its known alternates if it doesn't exist.
+    pub max_file: Option<PathBuf>,
+
+    /// Base delay in milliseconds between ddcci read attempts, multiplied by
+    /// `ddcci_sleep_multiplier`.
+    #[default(40)]
+    pub ddcci_read_base_ms: u64,
+
+    /// Luminance of the device at its hardware max brightness, in nits (cd/m²). Required to set
+    /// the brightness in nits.
+    pub max_nits: Option<f64>,
+
+    /// Points of a piecewise-linear curve mapping the brightness to the corrected brightness,
+    /// e.g. to correct a measured brightness response. Applied before the calibration window.
+    pub curve_points: Option<Vec<[f64; 2]>>,
+
+    /// Whether to fall back to writing sysfs (or the privileged helper) when writing via logind
+    /// fails. Defaults to disabled for ddcci devices, where it would hide the real DDC error,
+    /// and enabled otherwise.
+    pub sysfs_fallback: Option<bool>,
+
+    /// Ceiling of the raw brightness, as a fraction (0.0 to 1.0) of the hardware max, enforced on
+    /// every write after all other settings, e.g. to protect an OLED panel from burn-in. Unlike
+    /// `calibration` the range below it isn't remapped, brightness above it is just cut off.
+    pub max_brightness_cap: Option<f64>,
+
+    /// Distance, as a fraction (0.0 to 0.05) of the calibrated range, from the calibration limits
+    /// within which the read brightness is reported as exactly 0.0 or 1.0, so that it doesn't
+    /// flicker between `0.0` and tiny values at the limits.
+    pub calibration_snap: f64,
+}
+
+impl DeviceConfig {
+    /// Check that all values are in the ranges accepted by the config file.
+    ///
+    /// Note that `calibration`, `offset` and `calibration_snap` are fractions (0.0 to 1.0) here,
+    /// while they are percentages in the config file.
+    pub fn validate(&self) -> Result<()> {
+        let [low, high] = self.calibration.map(|val| val * 100.0);
+        let mut checks = vec![
+            check_finite_in_range("root_scaling", self.root_scaling, ROOT_SCALDING_RANGE),
+            check_finite_in_range("gamma", self.gamma, GAMMA_RANGE),
+            check_finite_in_range("calibration", low, CALIBRATION_RANGE),
+            check_finite_in_range("calibration", high, CALIBRATION_RANGE),
+            check_finite_in_range("offset", self.offset * 100.0, OFFSET_RANGE),
+            check_finite_in_range(
+                "calibration_snap",
+                self.calibration_snap * 100.0,
+                CALIBRATION_SNAP_RANGE,
+            ),
+            check_finite_in_range("weight", self.weight, WEIGHT_RANGE),
+            check_finite_in_range(
+                "ddcci_sleep_multiplier",
+                self.ddcci_sleep_multiplier,
+                DDCCI_SLEEP_MULTIPLIER_RANGE,
+            ),
+        ];
+        if let Some(max_brightness_cap) = self.max_brightness_cap {
+            checks.push(check_finite_in_range(
+                "max_brightness_cap",
+                max_brightness_cap * 100.0,
+                MAX_BRIGHTNESS_CAP_RANGE,
+            ));
+        }
+        if let Some(max_nits) = self.max_nits {
+            checks.push(check_finite_in_range("max_nits", max_nits, MAX_NITS_RANGE));
+        }
+        checks
+            .into_iter()
+            .collect::<std::result::Result<(), String>>()
+            .map_err(CalibrightError::Other)?;
+        if low > high {
+            return Err(CalibrightError::InvalidScaleParameters { low, high });
+        }
+
+        let base_ms = self.ddcci_read_base_ms;
+        if !DDCCI_READ_BASE_MS_RANGE.contains(&base_ms) {
+            return Err(CalibrightError::Other(format!(
+                "Invalid ddcci_read_base_ms {base_ms}, expected a number in the range of 1 to 1000"
+            )));
+        }
+
+        if let Some(curve_points) = &self.curve_points {
+            check_curve_points(curve_points)
+                .map_err(|e| CalibrightError::Other(format!("Invalid curve_points: {e}")))?;
+        }
+
+        if self.ddcci_max_tries_write_read == 0 {
+            return Err(CalibrightError::Other(
+                "Invalid ddcci_max_tries_write_read 0, expected at least 1".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the config if [`DeviceConfig::validate`] succeeds.
+    pub fn validated(self) -> Result<Self> {
+        self.validate()?;
+        Ok(self)
+    }
+}
+
+impl UnresolvedDeviceConfig {
+    /// Fill in the values that aren't set from `base`. The alias is never inherited.
+    fn resolve(&self, base: &DeviceConfig) -> DeviceConfig {
+        DeviceConfig {
+            root_scaling: self.root_scaling.unwrap_or(base.root_scaling),
+            ddcci_sleep_multiplier: self
+                .ddcci_sleep_multiplier
+                .unwrap_or(base.ddcci_sleep_multiplier),
+            ddcci_max_tries_write_read: self
+                .ddcci_max_tries_write_read
+                .unwrap_or(base.ddcci_max_tries_write_read),
+            calibration: self.calibration.unwrap_or(base.calibration),
+            calibration_set: self.calibration.is_some() || base.calibration_set,
+            gamma: self.gamma.unwrap_or(base.gamma),
+            offset: self.offset.unwrap_or(base.offset),
+            read_hysteresis: self.read_hysteresis.unwrap_or(base.read_hysteresis),
+            weight: self.weight.unwrap_or(base.weight),
+            alias: self.alias.clone(),
+            invert: self.invert.unwrap_or(base.invert),
+            write_file: self.write_file.clone().or_else(|| base.write_file.clone()),
+            max_file: self.max_file.clone().or_else(|| base.max_file.clone()),
+            ddcci_read_base_ms: self.ddcci_read_base_ms.unwrap_or(base.ddcci_read_base_ms),
+            max_nits: self.max_nits.or(base.max_nits),
+            curve_points: self
+                .curve_points
+                .clone()
+                .or_else(|| base.curve_points.clone()),
+            sysfs_fallback: self.sysfs_fallback.or(base.sysfs_fallback),
+            max_brightness_cap: self.max_brightness_cap.or(base.max_brightness_cap),
+            calibration_snap: self.calibration_snap.unwrap_or(base.calibration_snap),
+        }
+    }
 }
 
 #[derive(Deserialize, Clone, Default)]
 #[serde(default)]
 struct UnresolvedCalibrightConfig {
     global: UnresolvedDeviceConfig,
+    ddcci: Option<UnresolvedDeviceConfig>,
+    #[serde(deserialize_with = "deserialize_profiles")]
+    profiles: HashMap<String, HashMap<String, f64>>,
+    #[serde(deserialize_with = "deserialize_schedule")]
+    schedule: Vec<ScheduleEntry>,
     #[serde(flatten)]
     overrides: HashMap<String, UnresolvedDeviceConfig>,
 }
@@ -108,6 +586,9 @@ struct UnresolvedCalibrightConfig {
 /// Reads in the calibright configuration file
 pub struct CalibrightConfig {
     global: DeviceConfig,
+    ddcci: Option<DeviceConfig>,
+    profiles: HashMap<String, HashMap<String, f64>>,
+    schedule: Vec<ScheduleEntry>,
     overrides: HashMap<String, DeviceConfig>,
 }
 
@@ -124,34 +605,80 @@ impl UnresolvedCalibrightConfig {
                 .ddcci_max_tries_write_read
                 .unwrap_or(defaults.ddcci_max_tries_write_read),
             calibration: self.global.calibration.unwrap_or(defaults.calibration),
+            calibration_set: self.global.calibration.is_some() || defaults.calibration_set,
+            gamma: self.global.gamma.unwrap_or(defaults.gamma),
+            offset: self.global.offset.unwrap_or(defaults.offset),
+            read_hysteresis: self
+                .global
+                .read_hysteresis
+                .unwrap_or(defaults.read_hysteresis),
+            weight: self.global.weight.unwrap_or(defaults.weight),
+            alias: None,
+            invert: self.global.invert.unwrap_or(defaults.invert),
+            write_file: self
+                .global
+                .write_file
+                .clone()
+                .or_else(|| defaults.write_file.clone()),
+            max_file: self
+                .global
+                .max_file
+                .clone()
+                .or_else(|| defaults.max_file.clone()),
+            ddcci_read_base_ms: self
+                .global
+                .ddcci_read_base_ms
+                .unwrap_or(defaults.ddcci_read_base_ms),
+            max_nits: self.global.max_nits.or(defaults.max_nits),
+            curve_points: self
+                .global
+                .curve_points
+                .clone()
+                .or_else(|| defaults.curve_points.clone()),
+            sysfs_fallback: self.global.sysfs_fallback.or(defaults.sysfs_fallback),
+            max_brightness_cap: self
+                .global
+                .max_brightness_cap
+                .or(defaults.max_brightness_cap),
+            calibration_snap: self
+                .global
+                .calibration_snap
+                .unwrap_or(defaults.calibration_snap),
         };
 
+        // `ddcci` devices share the `[ddcci]` section, if there is one
+        let ddcci = self.ddcci.as_ref().map(|ddcci| DeviceConfig {
+            alias: None,
+            ..ddcci.resolve(&global)
+        });
+
         let mut resolved_overrides = HashMap::<String, DeviceConfig>::new();
 
         for (device_name, device_config) in &self.overrides {
-            resolved_overrides.insert(
-                device_name.to_owned(),
-                DeviceConfig {
-                    root_scaling: device_config.root_scaling.unwrap_or(global.root_scaling),
-                    ddcci_sleep_multiplier: device_config
-                        .ddcci_sleep_multiplier
-                        .unwrap_or(global.ddcci_sleep_multiplier),
-                    ddcci_max_tries_write_read: device_config
-                        .ddcci_max_tries_write_read
-                        .unwrap_or(global.ddcci_max_tries_write_read),
-                    calibration: device_config.calibration.unwrap_or(global.calibration),
-                },
-            );
+            let base = match &ddcci {
+                Some(ddcci) if is_ddcci(device_name) => ddcci,
+                _ => &global,
+            };
+            resolved_overrides.insert(device_name.to_owned(), device_config.resolve(base));
         }
 
         CalibrightConfig {
             global,
+            ddcci,
+            profiles: self.profiles.clone(),
+            schedule: self.schedule.clone(),
             overrides: resolved_overrides,
         }
     }
 }
 
 impl CalibrightConfig {
+    /// Reads the config from `$CALIBRIGHT_CONFIG` if set, otherwise from
+    /// `$XDG_CONFIG_HOME/calibright/config.toml` (or `config` without an extension). With the
+    /// `json` feature, `config.json` is read if there is no TOML config, and configs with a
+    /// `.json` extension are read as JSON. Other formats (e.g. YAML) are not detected, see
+    /// [`CalibrightConfig::from_deserializer`].
+    ///
     ///  Uses [`DeviceConfig::default`] for the default global values.
     pub async fn new() -> Result<Self> {
         CalibrightConfig::new_with_defaults(&DeviceConfig::default()).await
@@ -161,18 +688,109 @@ impl CalibrightConfig {
     pub async fn new_with_defaults(defaults: &DeviceConfig) -> Result<Self> {
         if let Some(config_path) = config_path().await? {
             debug!("config_path={}", config_path.display());
-            deserialize_toml_file(config_path).await
+            read_config_file(config_path).await
         } else {
             Ok(UnresolvedCalibrightConfig::default())
         }
         .map(|config| config.resolve(defaults))
     }
 
-    pub(crate) fn get_device_config(&self, device_name: &String) -> DeviceConfig {
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    /// Build the config from JSON with the same structure as the TOML config file, e.g.
+    /// `{"global": {"calibration": [5.0, 95.0]}}`, using a custom [`DeviceConfig`] for the
+    /// default global values. `null` values are treated as missing.
+    ///
+    /// The same validation is applied as when reading the TOML config file.
+    pub fn from_json_str(json: &str, defaults: &DeviceConfig) -> Result<Self> {
+        crate::json::deserialize_json_str::<UnresolvedCalibrightConfig>(json)
+            .map(|config| config.resolve(defaults))
+            .map_err(|message| CalibrightError::Config {
+                path: None,
+                message,
+            })
+    }
+
+    /// Build the config from any serde [`Deserializer`], e.g. one from `serde_yaml`, using a
+    /// custom [`DeviceConfig`] for the default global values.
+    ///
+    /// The same validation is applied as when reading the TOML config file.
+    pub fn from_deserializer<'de, D>(
+        deserializer: D,
+        defaults: &DeviceConfig,
+    ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        UnresolvedCalibrightConfig::deserialize(deserializer).map(|config| config.resolve(defaults))
+    }
+
+    /// Check that `toml` is a valid config, e.g. before saving it from a settings UI.
+    ///
+    /// Returns a [`CalibrightError::Config`] describing the first problem, including its line.
+    pub fn validate(toml: &str) -> Result<()> {
+        deserialize_toml_str::<UnresolvedCalibrightConfig>(toml)
+            .map(|config| {
+                config.resolve(&DeviceConfig::default());
+            })
+            .map_err(|message| CalibrightError::Config {
+                path: None,
+                message,
+            })
+    }
+
+    /// The resolved global config, used for devices without an override.
+    pub fn global(&self) -> &DeviceConfig {
+        &self.global
+    }
+
+    /// The target brightness (0.0..=1.0) of each device name (or `global` for the other devices)
+    /// of the `[profiles.<name>]` section with the given name.
+    pub fn profile(&self, name: &str) -> Option<&HashMap<String, f64>> {
+        self.profiles.get(name)
+    }
+
+    /// The config of a device at the given minute of the day: the values of the `[[schedule]]`
+    /// window containing it, if any, on top of [`CalibrightConfig::get_device_config`].
+    pub(crate) fn get_scheduled_device_config(
+        &self,
+        device_name: &String,
+        connector: Option<&str>,
+        minute: u32,
+    ) -> DeviceConfig {
+        let base = self.get_device_config(device_name, connector);
+        match self.schedule.iter().find(|entry| entry.contains(minute)) {
+            Some(entry) => DeviceConfig {
+                alias: base.alias.clone(),
+                ..entry.config.resolve(&base)
+            },
+            None => base,
+        }
+    }
+
+    /// The section names of the device overrides, i.e. device or connector names.
+    pub(crate) fn override_names(&self) -> impl Iterator<Item = &str> {
+        self.overrides.keys().map(String::as_str)
+    }
+
+    /// The config of a device: its override (by device name, or else by connector name), the
+    /// `[ddcci]` section, or the global config.
+    pub(crate) fn get_device_config(
+        &self,
+        device_name: &String,
+        connector: Option<&str>,
+    ) -> DeviceConfig {
         debug!("{}", device_name);
-        if let Some(device_config) = self.overrides.get(device_name) {
+        if let Some(device_config) = self
+            .overrides
+            .get(device_name)
+            .or_else(|| self.overrides.get(connector?))
+        {
             debug!("{:?}", device_config);
             device_config.clone()
+        } else if let Some(ddcci) = self.ddcci.as_ref().filter(|_| is_ddcci(device_name)) {
+            debug!("using ddcci config");
+            ddcci.clone()
         } else {
             debug!("using global config");
             self.global.clone()
@@ -180,19 +798,251 @@ impl CalibrightConfig {
     }
 }
 
+/// Whether the device is a DDC/CI monitor, which uses the `[ddcci]` section.
+fn is_ddcci(device_name: &str) -> bool {
+    device_name.starts_with(DDCCI_PREFIX)
+        || cfg!(feature = "i2c") && device_name.starts_with(I2C_PREFIX)
+}
+
 async fn config_path() -> Result<Option<PathBuf>> {
+    // An explicitly set config has to exist
+    if let Some(path) = std::env::var_os(ENV_CONFIG).filter(|path| !path.is_empty()) {
+        let path = PathBuf::from(path);
+        return if config_file_exists(&path).await? {
+            Ok(Some(path))
+        } else {
+            Err(CalibrightError::Config {
+                path: Some(path),
+                message: format!("not found (set by ${ENV_CONFIG})"),
+            })
+        };
+    }
+
     let mut xdg_config = match config_dir() {
         Some(xdg_config) => xdg_config,
         None => return Err(CalibrightError::Other("Could not get xdg_config".into())),
     };
     xdg_config.push("calibright");
     xdg_config.push("config");
-    Ok(if tokio::fs::try_exists(&xdg_config).await? {
-        Some(xdg_config)
+    let mut candidates = vec![xdg_config.clone(), xdg_config.with_extension("toml")];
+    if cfg!(feature = "json") {
+        candidates.push(xdg_config.with_extension("json"));
+    }
+    for path in candidates {
+        if config_file_exists(&path).await? {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Read the config file at `config_path`, with errors reading it (e.g. permission denied) as
+/// config errors.
+async fn read_config_file(config_path: PathBuf) -> Result<UnresolvedCalibrightConfig> {
+    #[cfg(feature = "json")]
+    let config = if config_path.extension().is_some_and(|ext| ext == "json") {
+        deserialize_json_file(&config_path).await
     } else {
-        xdg_config.set_extension("toml");
-        tokio::fs::try_exists(&xdg_config)
-            .await?
-            .then_some(xdg_config)
+        deserialize_toml_file(&config_path).await
+    };
+    #[cfg(not(feature = "json"))]
+    let config = deserialize_toml_file(&config_path).await;
+    config.map_err(|e| match e {
+        CalibrightError::Io(e) => CalibrightError::Config {
+            path: Some(config_path),
+            message: e.to_string(),
+        },
+        e => e,
     })
 }
+
+/// Only a missing file means there is no config, anything else is reported.
+async fn config_file_exists(path: &Path) -> Result<bool> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_file() => Ok(true),
+        Ok(_) => Err(CalibrightError::Config {
+            path: Some(path.to_path_buf()),
+            message: "not a file".into(),
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(CalibrightError::Config {
+            path: Some(path.to_path_buf()),
+            message: e.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fs::tests::config;
+
+    /// The message of the error `toml` is rejected with.
+    fn config_error(toml: &str) -> String {
+        match CalibrightConfig::validate(toml) {
+            Err(CalibrightError::Config { message, .. }) => message,
+            res => panic!("expected a config error for {toml:?}, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected() {
+        let keys = [
+            "root_scaling",
+            "gamma",
+            "offset",
+            "weight",
+            "max_nits",
+            "max_brightness_cap",
+            "calibration_snap",
+            "ddcci_sleep_multiplier",
+        ];
+        for value in ["nan", "inf", "-inf"] {
+            let mut tomls: Vec<String> = keys
+                .iter()
+                .map(|key| format!("[global]\n{key} = {value}"))
+                .collect();
+            tomls.push(format!("[global]\ncalibration = [{value}, 50.0]"));
+            tomls.push(format!("[global]\ncalibration = [0.0, {value}]"));
+            tomls.push(format!("[profiles.night]\nglobal = {value}"));
+            for toml in tomls {
+                let message = config_error(&toml);
+                assert!(
+                    message.contains("NaN and infinity are not allowed"),
+                    "{toml}: {message}"
+                );
+            }
+        }
+
+        for config in [
+            DeviceConfig {
+                gamma: f64::NAN,
+                ..DeviceConfig::default()
+            },
+            DeviceConfig {
+                ddcci_sleep_multiplier: f64::INFINITY,
+                ..DeviceConfig::default()
+            },
+        ] {
+            let err = config.validate().unwrap_err().to_string();
+            assert!(err.contains("NaN and infinity are not allowed"), "{err}");
+        }
+        let config = DeviceConfig {
+            ddcci_sleep_multiplier: 0.0,
+            ..DeviceConfig::default()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("greater than 0.0"), "{err}");
+    }
+
+    #[test]
+    fn values_are_rejected_just_outside_of_their_ranges() {
+        for (toml, expected) in [
+            ("root_scaling = 0.09", "in the range of 0.1 to 10.0"),
+            ("gamma = 10.01", "in the range of 0.1 to 10.0"),
+            ("offset = 100.0", "in the range of 0.0 to 100.0 (exclusive)"),
+            ("offset = -0.01", "in the range of 0.0 to 100.0 (exclusive)"),
+            ("weight = -0.01", "of at least 0.0"),
+            ("max_nits = 0.0", "greater than 0.0"),
+            ("ddcci_sleep_multiplier = 0.0", "greater than 0.0"),
+            ("ddcci_sleep_multiplier = -1.0", "greater than 0.0"),
+            ("max_brightness_cap = 0.99", "in the range of 1.0 to 100.0"),
+            ("calibration_snap = -0.01", "in the range of 0.0 to 5.0"),
+            ("calibration_snap = 5.01", "in the range of 0.0 to 5.0"),
+            (
+                "calibration = [0.0, 100.01]",
+                "in the range of 0.0 to 100.0",
+            ),
+        ] {
+            let message = config_error(&format!("[global]\n{toml}"));
+            assert!(message.contains(expected), "{toml}: {message}");
+        }
+        let message = config_error("[profiles.night]\nglobal = 100.01");
+        assert!(message.contains("night.global"), "{message}");
+    }
+
+    #[test]
+    fn values_are_accepted_at_their_bounds() {
+        let bounds = config(
+            "[global]\nroot_scaling = 10.0\ngamma = 0.1\noffset = 0.0\nweight = 0.0\n\
+             max_brightness_cap = 100.0\ncalibration_snap = 5.0\ncalibration = [0.0, 100.0]",
+        );
+        let global = bounds.global();
+        assert_eq!(global.root_scaling, 10.0);
+        assert_eq!(global.gamma, 0.1);
+        assert_eq!(global.calibration_snap, 0.05);
+        assert_eq!(global.max_brightness_cap, Some(1.0));
+        global.validate().unwrap();
+
+        let profiles = config("[profiles.night]\nglobal = 20.0\nddcci5 = 35.0");
+        let night = profiles.profile("night").unwrap();
+        assert_eq!(night["global"], 0.2);
+        assert_eq!(night["ddcci5"], 0.35);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_config_is_resolved_and_validated_like_toml() {
+        let defaults = DeviceConfig::default();
+        let config = CalibrightConfig::from_json_str(
+            r#"{"global": {"calibration": [5, 95], "gamma": null}, "DP-1": {"invert": true}}"#,
+            &defaults,
+        )
+        .unwrap();
+        assert_eq!(config.global().calibration, [0.05, 0.95]);
+        assert_eq!(config.global().gamma, 1.0);
+        let dp1 = config.get_device_config(&"ddcci5".into(), Some("DP-1"));
+        assert!(dp1.invert);
+        assert_eq!(dp1.calibration, [0.05, 0.95]);
+
+        let err =
+            CalibrightConfig::from_json_str(r#"{"global": {"calibration": [5, 200]}}"#, &defaults)
+                .err()
+                .unwrap();
+        assert!(matches!(err, CalibrightError::Config { .. }), "{err}");
+    }
+
+    #[test]
+    fn max_tries_must_be_at_least_one() {
+        let message = config_error("[global]\nddcci_max_tries_write_read = 0");
+        assert!(message.contains("at least 1"), "{message}");
+        CalibrightConfig::validate("[global]\nddcci_max_tries_write_read = 1").unwrap();
+    }
+
+    #[test]
+    fn finite_values_are_accepted() {
+        CalibrightConfig::validate("[global]\ncalibration = [5.0, 95.0]\nroot_scaling = 2.0")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn config_paths_that_cant_be_read_are_errors() {
+        let dir = std::env::temp_dir().join(format!("calibright-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config");
+        std::fs::write(&file, "").unwrap();
+
+        assert!(!config_file_exists(&dir.join("missing")).await.unwrap());
+        assert!(config_file_exists(&file).await.unwrap());
+        // A directory in place of the config
+        let result = config_file_exists(&dir).await;
+        assert!(
+            matches!(result, Err(CalibrightError::Config { message, .. }) if message == "not a file")
+        );
+        // A path that can't be looked up, here because a parent is a file
+        let result = config_file_exists(&file.join("config")).await;
+        assert!(matches!(
+            result,
+            Err(CalibrightError::Config { path: Some(_), .. })
+        ));
+
+        // A config that can't be read, here because it became a directory after the check
+        let result = read_config_file(dir.clone()).await;
+        assert!(
+            matches!(result, Err(CalibrightError::Config { path: Some(path), .. }) if path == dir)
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
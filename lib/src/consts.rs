@@ -1,11 +1,57 @@
-use std::ops::RangeInclusive;
+use std::ops::{Bound, Range, RangeFrom, RangeInclusive};
+use std::time::Duration;
 
 /// Location of backlight devices
 pub const DEVICES_PATH: &str = "/sys/class/backlight";
 
+/// Location of LED devices (e.g. keyboard backlights)
+pub const LEDS_PATH: &str = "/sys/class/leds";
+
+/// Environment variable with the device regex used when none is passed to the builder
+pub const ENV_DEVICE: &str = "CALIBRIGHT_DEVICE";
+
+/// Environment variable with the path of the config file, used instead of the XDG config path
+pub const ENV_CONFIG: &str = "CALIBRIGHT_CONFIG";
+
+/// Brightness values this close to 0.0 or 1.0 are reported as exactly 0.0 or 1.0
+pub const BRIGHTNESS_SNAP_EPSILON: f64 = 1e-9;
+
+/// How often the devices are read while waiting for them to reach a brightness
+pub const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Prefix of the names of ACPI firmware backlights, which usually control the same panel as a
+/// native (e.g. `intel_backlight`) backlight
+pub const ACPI_VIDEO_PREFIX: &str = "acpi_video";
+
+/// Location of the I2C bus devices (`i2c-*`), used to talk DDC/CI to monitors directly
+#[cfg(feature = "i2c")]
+pub const I2C_DEVICES_PATH: &str = "/dev";
+
+/// Location of the I2C bus devices in sysfs
+#[cfg(feature = "i2c")]
+pub const I2C_SYSFS_PATH: &str = "/sys/class/i2c-dev";
+
+/// Prefix of the names of I2C bus devices
+pub const I2C_PREFIX: &str = "i2c-";
+
+/// Prefix of the names of DDC/CI monitors, which use the `[ddcci]` config section
+pub const DDCCI_PREFIX: &str = "ddcci";
+
 /// Filename for device's max brightness
 pub const FILE_MAX_BRIGHTNESS: &str = "max_brightness";
 
+/// Names some drivers use for the max brightness instead of `max_brightness`, tried in order
+pub const ALT_MAX_FILES: [&str; 2] = ["brightness_max", "max_level"];
+
+/// Largest `max_brightness` accepted from a driver, anything above is assumed to be bogus
+pub const MAX_BRIGHTNESS_LIMIT: u32 = 1 << 24;
+
+/// Filename for the lowest usable raw brightness, exposed by some drivers
+pub const FILE_BRIGHTNESS_MIN: &str = "brightness_min";
+
+/// Filename for how the brightness relates to the perceived brightness (backlights only)
+pub const FILE_SCALE: &str = "scale";
+
 /// Filename for current brightness.
 pub const FILE_BRIGHTNESS: &str = "actual_brightness";
 
@@ -14,6 +60,18 @@ pub const FILE_BRIGHTNESS: &str = "actual_brightness";
 /// This may be fixed in the new 5.7 kernel?
 pub const FILE_BRIGHTNESS_AMD: &str = "brightness";
 
+/// LED devices don't have an actual_brightness file, so the 'brightness' file is used instead.
+pub const FILE_BRIGHTNESS_LEDS: &str = "brightness";
+
+/// Notifies of brightness changes made by the hardware (e.g. firmware handled brightness keys)
+pub const FILE_BRIGHTNESS_HW_CHANGED: &str = "brightness_hw_changed";
+
+/// Turns the backlight power on and off, independent of the brightness (backlights only)
+pub const FILE_BL_POWER: &str = "bl_power";
+
+/// The kind of backlight interface: `raw`, `platform` or `firmware` (backlights only)
+pub const FILE_TYPE: &str = "type";
+
 /// set the requested brightness level
 pub const FILE_BRIGHTNESS_WRITE: &str = "brightness";
 
@@ -22,3 +80,34 @@ pub const ROOT_SCALDING_RANGE: RangeInclusive<f64> = 0.1..=10.;
 
 /// Range of valid values for `Calibration`
 pub const CALIBRATION_RANGE: RangeInclusive<f64> = 0.0..=100.;
+
+/// Range of valid values for `ddcci_read_base_ms`
+pub const DDCCI_READ_BASE_MS_RANGE: RangeInclusive<u64> = 1..=1000;
+
+/// Range of valid values for `calibration_snap`
+pub const CALIBRATION_SNAP_RANGE: RangeInclusive<f64> = 0.0..=5.;
+
+/// Range of valid values for `max_brightness_cap`
+pub const MAX_BRIGHTNESS_CAP_RANGE: RangeInclusive<f64> = 1.0..=100.;
+
+/// Range of valid values for `gamma`
+pub const GAMMA_RANGE: RangeInclusive<f64> = 0.1..=10.;
+
+/// Range of valid values for `offset`
+pub const OFFSET_RANGE: Range<f64> = 0.0..100.;
+
+/// Range of valid values for `weight`
+pub const WEIGHT_RANGE: RangeFrom<f64> = 0.0..;
+
+/// Range of valid values for `max_nits`
+pub const MAX_NITS_RANGE: (Bound<f64>, Bound<f64>) = (Bound::Excluded(0.0), Bound::Unbounded);
+
+/// Range of valid values for `ddcci_sleep_multiplier`
+pub const DDCCI_SLEEP_MULTIPLIER_RANGE: (Bound<f64>, Bound<f64>) =
+    (Bound::Excluded(0.0), Bound::Unbounded);
+
+/// Number of minutes in a day, the resolution of `[[schedule]]` windows
+pub const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Range of valid brightness values used by the library
+pub const BRIGHTNESS_RANGE: RangeInclusive<f64> = 0.0..=1.0;
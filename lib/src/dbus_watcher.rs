@@ -0,0 +1,119 @@
+use crate::device::Subsystem;
+
+use futures_util::StreamExt;
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, Result};
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use zbus::fdo::PropertiesProxy;
+use zbus::Connection;
+
+make_log_macro!(debug, "calibright_dbus_watcher");
+
+/// Interface of the GNOME settings daemon that exposes the screen brightness.
+const GSD_SCREEN_INTERFACE: &str = "org.gnome.SettingsDaemon.Power.Screen";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.UPower.KbdBacklight",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower/KbdBacklight"
+)]
+trait KbdBacklight {
+    #[zbus(signal)]
+    fn brightness_changed(&self, value: i32) -> zbus::Result<()>;
+}
+
+/// Translates brightness change signals on D-Bus into [`notify`] modify events of the sysfs
+/// class directory of the affected subsystem.
+///
+/// This helps where changes of the sysfs files aren't reported (e.g. in containers or on some
+/// virtualized filesystems), as long as UPower (keyboard backlights) or the GNOME settings daemon
+/// (screen backlights) is running.
+pub struct DBusWatcher {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl DBusWatcher {
+    pub fn new(subsystems: &[Subsystem], tx: Sender<Result<Event>>) -> Self {
+        let mut tasks = Vec::new();
+        if subsystems.contains(&Subsystem::Leds) {
+            tasks.push(tokio::spawn(watch_kbd_backlight(tx.clone())));
+        }
+        if subsystems.contains(&Subsystem::Backlight) {
+            tasks.push(tokio::spawn(watch_gsd_screen(tx)));
+        }
+
+        Self { tasks }
+    }
+
+    /// Stop watching, e.g. on shutdown.
+    pub fn stop(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for DBusWatcher {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn changed_event(subsystem: Subsystem) -> Event {
+    Event::new(EventKind::Modify(ModifyKind::Any)).add_path(subsystem.path().into())
+}
+
+async fn watch_kbd_backlight(tx: Sender<Result<Event>>) {
+    let res: zbus::Result<()> = async {
+        let connection = Connection::system().await?;
+        let proxy = KbdBacklightProxy::new(&connection).await?;
+        let mut changes = proxy.receive_brightness_changed().await?;
+        while changes.next().await.is_some() {
+            debug!("UPower keyboard backlight changed");
+            if tx.send(Ok(changed_event(Subsystem::Leds))).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(e) = res {
+        debug!("not watching UPower: {e}");
+    }
+}
+
+async fn watch_gsd_screen(tx: Sender<Result<Event>>) {
+    let res: zbus::Result<()> = async {
+        let connection = Connection::session().await?;
+        let proxy = PropertiesProxy::builder(&connection)
+            .destination("org.gnome.SettingsDaemon.Power")?
+            .path("/org/gnome/SettingsDaemon/Power")?
+            .build()
+            .await?;
+        let mut changes = proxy.receive_properties_changed().await?;
+        while let Some(change) = changes.next().await {
+            let args = change.args()?;
+            if args.interface_name() != GSD_SCREEN_INTERFACE
+                || !args.changed_properties().contains_key("Brightness")
+            {
+                continue;
+            }
+            debug!("GNOME screen brightness changed");
+            if tx
+                .send(Ok(changed_event(Subsystem::Backlight)))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(e) = res {
+        debug!("not watching the GNOME settings daemon: {e}");
+    }
+}
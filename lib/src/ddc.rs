@@ -0,0 +1,116 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::errors::*;
+
+make_log_macro!(debug, "calibright_ddc");
+
+/// `ioctl` setting the address of the device on the I2C bus that reads and writes go to.
+const I2C_SLAVE: libc::c_ulong = 0x0703;
+
+/// I2C address of the display for DDC/CI.
+const DDC_ADDRESS: u8 = 0x37;
+
+/// Source address of the host in DDC/CI messages.
+const HOST_ADDRESS: u8 = 0x51;
+
+/// Address the checksum of replies from the display starts with.
+const REPLY_CHECKSUM_BASE: u8 = 0x50;
+
+/// Length of a reply to a Get VCP Feature request, including the checksum.
+const GET_VCP_REPLY_LEN: usize = 11;
+
+const GET_VCP_REQUEST: u8 = 0x01;
+const GET_VCP_REPLY: u8 = 0x02;
+const SET_VCP_REQUEST: u8 = 0x03;
+
+/// VCP feature code of the brightness (luminance).
+pub const VCP_BRIGHTNESS: u8 = 0x10;
+
+/// Open the I2C bus, addressing the display on it.
+fn open(bus: &Path) -> io::Result<File> {
+    let file = OpenOptions::new().read(true).write(true).open(bus)?;
+    // SAFETY: plain ioctl on a valid fd, the address is passed by value
+    let res = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            I2C_SLAVE as _,
+            libc::c_ulong::from(DDC_ADDRESS),
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Wrap `payload` in a DDC/CI message: the source address, the length and the checksum.
+fn message(payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![HOST_ADDRESS, 0x80 | payload.len() as u8];
+    message.extend_from_slice(payload);
+    let checksum = message
+        .iter()
+        .fold(DDC_ADDRESS << 1, |checksum, byte| checksum ^ byte);
+    message.push(checksum);
+    message
+}
+
+/// Read the `(current, max)` values of a VCP feature from the display on the I2C bus.
+///
+/// `reply_delay` is how long the display is given to prepare the reply, see section 4.3 of
+/// <https://glenwing.github.io/docs/VESA-DDCCI-1.1.pdf>.
+pub async fn get_vcp(bus: &Path, code: u8, reply_delay: Duration) -> Result<(u16, u16)> {
+    let bus = bus.to_path_buf();
+    tokio::task::spawn_blocking(move || get_vcp_blocking(&bus, code, reply_delay))
+        .await
+        .map_err(|e| CalibrightError::Other(e.to_string()))?
+}
+
+fn get_vcp_blocking(bus: &Path, code: u8, reply_delay: Duration) -> Result<(u16, u16)> {
+    let mut file = open(bus)?;
+    file.write_all(&message(&[GET_VCP_REQUEST, code]))?;
+    std::thread::sleep(reply_delay);
+    let mut reply = [0; GET_VCP_REPLY_LEN];
+    file.read_exact(&mut reply)?;
+    debug!("{} replied {:02x?}", bus.display(), reply);
+
+    let checksum = reply[..GET_VCP_REPLY_LEN - 1]
+        .iter()
+        .fold(REPLY_CHECKSUM_BASE, |checksum, byte| checksum ^ byte);
+    if checksum != reply[GET_VCP_REPLY_LEN - 1]
+        || reply[1] & 0x7f != (GET_VCP_REPLY_LEN - 3) as u8
+        || reply[2] != GET_VCP_REPLY
+        || reply[4] != code
+    {
+        return Err(CalibrightError::Other(format!(
+            "Invalid DDC/CI reply from {}: {reply:02x?}",
+            bus.display()
+        )));
+    }
+    if reply[3] != 0 {
+        return Err(CalibrightError::Other(format!(
+            "VCP feature {code:#04x} is not supported by the display on {}",
+            bus.display()
+        )));
+    }
+
+    Ok((
+        u16::from_be_bytes([reply[8], reply[9]]),
+        u16::from_be_bytes([reply[6], reply[7]]),
+    ))
+}
+
+/// Set a VCP feature of the display on the I2C bus.
+pub async fn set_vcp(bus: &Path, code: u8, value: u16) -> Result<()> {
+    let bus: PathBuf = bus.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let [high, low] = value.to_be_bytes();
+        open(&bus)?.write_all(&message(&[SET_VCP_REQUEST, code, high, low]))?;
+        Ok(())
+    })
+    .await
+    .map_err(|e| CalibrightError::Other(e.to_string()))?
+}
@@ -1,17 +1,21 @@
-use crate::config::DeviceConfig;
+use crate::brightness::{OverflowPolicy, ReadErrorPolicy};
+use crate::config::{CalibrightConfig, DeviceConfig};
 use crate::consts::*;
 use crate::errors::*;
+use crate::fs::{FsProvider, TokioFs};
 use crate::util::*;
 
+use smart_default::SmartDefault;
 use std::cmp::max;
 use std::ffi::{OsStr, OsString};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
-#[cfg(feature = "watch")]
-use std::time::Instant;
-use tokio::fs::OpenOptions;
-use tokio::io::AsyncWriteExt;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
+use zbus::names::{BusName, InterfaceName, WellKnownName};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
 use zbus::Connection;
 
 make_log_macro!(debug, "calibright_device");
@@ -23,137 +27,1876 @@ make_log_macro!(debug, "calibright_device");
 )]
 trait Session {
     fn set_brightness(&self, subsystem: &str, name: &str, brightness: u32) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+
+    fn list_sessions(&self) -> zbus::Result<Vec<(String, u32, String, String, OwnedObjectPath)>>;
+}
+
+/// The D-Bus names used to reach logind, e.g. to use elogind or another compatible service
+/// published under a different name.
+#[derive(Clone, Debug)]
+pub struct LogindNames {
+    /// Bus name of the service.
+    pub service: BusName<'static>,
+    /// Interface of the session objects.
+    pub session_interface: InterfaceName<'static>,
+    /// Path of the caller's session object.
+    pub session_path: ObjectPath<'static>,
+}
+
+impl Default for LogindNames {
+    fn default() -> Self {
+        Self {
+            service: BusName::WellKnown(WellKnownName::from_static_str_unchecked(
+                "org.freedesktop.login1",
+            )),
+            session_interface: InterfaceName::from_static_str_unchecked(
+                "org.freedesktop.login1.Session",
+            ),
+            session_path: ObjectPath::from_static_str_unchecked(
+                "/org/freedesktop/login1/session/auto",
+            ),
+        }
+    }
+}
+
+impl LogindNames {
+    /// Parse the names, failing if any of them isn't a syntactically valid bus name, interface
+    /// name or object path respectively.
+    pub fn new(service: &str, session_interface: &str, session_path: &str) -> Result<Self> {
+        let invalid = |kind: &str, name: &str, e: zbus::names::Error| {
+            CalibrightError::Other(format!("Invalid logind {kind} {name:?}: {e}"))
+        };
+        Ok(Self {
+            service: BusName::try_from(service)
+                .map_err(|e| invalid("service", service, e))?
+                .into_owned(),
+            session_interface: InterfaceName::try_from(session_interface)
+                .map_err(|e| invalid("session interface", session_interface, e))?
+                .into_owned(),
+            session_path: ObjectPath::try_from(session_path)
+                .map_err(|e| invalid("session path", session_path, e.into()))?
+                .into_owned(),
+        })
+    }
+}
+
+/// Get a proxy for the session at the configured (`/auto`) session path.
+async fn auto_session_proxy(
+    dbus_conn: &Connection,
+    names: &LogindNames,
+) -> Result<SessionProxy<'static>> {
+    Ok(SessionProxy::builder(dbus_conn)
+        .destination(names.service.clone())?
+        .interface(names.session_interface.clone())?
+        .path(names.session_path.clone())?
+        .build()
+        .await?)
+}
+
+/// Get a proxy for the caller's logind session.
+///
+/// `/org/freedesktop/login1/session/auto` only resolves when the caller is part of a session, which
+/// isn't the case for e.g. systemd services, so fall back to looking up a session explicitly.
+async fn session_proxy(
+    dbus_conn: &Connection,
+    names: &LogindNames,
+) -> Result<SessionProxy<'static>> {
+    let auto_proxy = auto_session_proxy(dbus_conn, names).await?;
+    if auto_proxy.id().await.is_ok() {
+        return Ok(auto_proxy);
+    }
+    debug!("logind session path {} did not resolve", names.session_path);
+
+    let manager_proxy = ManagerProxy::builder(dbus_conn)
+        .destination(names.service.clone())?
+        .build()
+        .await?;
+    let session_path = match manager_proxy.get_session_by_pid(std::process::id()).await {
+        Ok(session_path) => session_path,
+        Err(e) => {
+            debug!("GetSessionByPID failed: {e}");
+            let uid = tokio::fs::metadata("/proc/self").await?.uid();
+            manager_proxy
+                .list_sessions()
+                .await?
+                .into_iter()
+                .find(|(_, session_uid, _, _, _)| *session_uid == uid)
+                .map(|(_, _, _, _, session_path)| session_path)
+                .ok_or_else(|| {
+                    CalibrightError::Other(format!("No logind session found for uid {uid}"))
+                })?
+        }
+    };
+    debug!("using logind session path {}", session_path.as_str());
+
+    Ok(SessionProxy::builder(dbus_conn)
+        .destination(names.service.clone())?
+        .interface(names.session_interface.clone())?
+        .path(session_path)?
+        .build()
+        .await?)
+}
+
+/// Find the DRM connector of the device in the given sysfs directory, i.e. the `card0-DP-1`
+/// directory above it. Native backlights are direct children of it, while DDC/CI monitors are
+/// found below the I2C adapter of the connector.
+pub(crate) async fn connector_name(fs: &dyn FsProvider, device_path: &Path) -> Option<String> {
+    let real_path = fs.canonicalize(&device_path.join("device")).await.ok()?;
+    real_path.ancestors().find_map(|path| {
+        let (card, connector) = path.file_name()?.to_str()?.split_once('-')?;
+        card.strip_prefix("card")?
+            .parse::<u32>()
+            .ok()
+            .map(|_| connector.to_string())
+    })
+}
+
+/// Find the file the max brightness of the device at `device_path` is read from: the `max_file`
+/// of the device config or [`DeviceOptions::max_file`] if set, otherwise the first of
+/// `max_brightness` and [`ALT_MAX_FILES`] that exists. `None` if the file doesn't exist.
+pub(crate) async fn find_max_file(
+    device_path: &Path,
+    config: &DeviceConfig,
+    options: &DeviceOptions,
+) -> Option<PathBuf> {
+    let candidates: Vec<&OsStr> = match (&config.max_file, &options.max_file) {
+        (Some(max_file), _) => vec![max_file.as_os_str()],
+        (None, Some(max_file)) => vec![max_file.as_os_str()],
+        (None, None) => std::iter::once(FILE_MAX_BRIGHTNESS)
+            .chain(ALT_MAX_FILES)
+            .map(OsStr::new)
+            .collect(),
+    };
+    for candidate in candidates {
+        let max_file = device_path.join(candidate);
+        if options.fs.exists(&max_file).await.unwrap_or(false) {
+            debug!("using {} as the max brightness", max_file.display());
+            return Some(max_file);
+        }
+    }
+
+    None
+}
+
+/// The sysfs class a device belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    /// Screen backlights, found in `/sys/class/backlight`.
+    Backlight,
+    /// LEDs such as keyboard backlights, found in `/sys/class/leds`.
+    Leds,
+    #[cfg(feature = "i2c")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "i2c")))]
+    /// DDC/CI monitors talked to directly over the I2C buses found in `/dev` (`i2c-*`), without
+    /// the `ddcci-backlight` driver. The device names are the bus names, e.g. `i2c-5`, and they
+    /// use the `[ddcci]` config section.
+    ///
+    /// Every bus is probed for a monitor, which can be slow, so narrow them down with the device
+    /// regex (e.g. to the connector name). Changes aren't watched.
+    I2c,
+}
+
+impl Subsystem {
+    /// Location of the devices of this subsystem.
+    pub fn path(self) -> &'static str {
+        match self {
+            Subsystem::Backlight => DEVICES_PATH,
+            Subsystem::Leds => LEDS_PATH,
+            #[cfg(feature = "i2c")]
+            Subsystem::I2c => I2C_DEVICES_PATH,
+        }
+    }
+
+    /// Location of the devices of this subsystem in sysfs.
+    pub(crate) fn sysfs_path(self) -> &'static str {
+        match self {
+            #[cfg(feature = "i2c")]
+            Subsystem::I2c => I2C_SYSFS_PATH,
+            _ => self.path(),
+        }
+    }
+
+    /// Name of this subsystem, as used by logind.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Subsystem::Backlight => "backlight",
+            Subsystem::Leds => "leds",
+            #[cfg(feature = "i2c")]
+            Subsystem::I2c => "i2c",
+        }
+    }
+}
+
+/// How the brightness of a backlight relates to the perceived brightness, as reported by the
+/// driver in the sysfs `scale` attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BacklightScale {
+    /// The driver doesn't know.
+    Unknown,
+    /// The brightness is linear to the light output, so usually needs `root_scaling`.
+    Linear,
+    /// The brightness already follows a curve, e.g. a perceptual one.
+    NonLinear,
+}
+
+impl BacklightScale {
+    fn parse(content: &str) -> Self {
+        match content.trim() {
+            "linear" => BacklightScale::Linear,
+            "non-linear" => BacklightScale::NonLinear,
+            _ => BacklightScale::Unknown,
+        }
+    }
+}
+
+/// How the brightness of a device was last written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WriteMethod {
+    /// Through logind over D-Bus.
+    Logind,
+    /// Directly to the sysfs brightness file.
+    Sysfs,
+    #[cfg(feature = "i2c")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "i2c")))]
+    /// Over DDC/CI on the I2C bus.
+    Ddc,
+    #[cfg(feature = "helper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "helper")))]
+    /// By running the privileged helper command.
+    PrivilegedHelper,
+}
+
+/// A point-in-time view of a single device's brightness.
+#[derive(Clone, Debug)]
+pub struct DeviceSnapshot {
+    /// Name of the device in sysfs.
+    pub name: OsString,
+    /// The sysfs class the device belongs to.
+    pub subsystem: Subsystem,
+    /// Raw brightness value as read from sysfs.
+    pub raw: u32,
+    /// Maximum raw brightness value supported by the device.
+    pub max: u32,
+    /// The scale reported by the driver, if it exposes one.
+    pub scale: Option<BacklightScale>,
+    /// Brightness based on the calibration settings, in range 0.0 to 1.0 (inclusive).
+    pub percent: f64,
+    /// The resolved configuration used by the device.
+    pub config: DeviceConfig,
+}
+
+/// What a device supports, determined when it is discovered, e.g. so that a UI can adapt its
+/// controls to each device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The backlight power can be switched independently of the brightness (`bl_power`).
+    pub can_power_off: bool,
+    /// The device is a monitor controlled over DDC/CI, so reads and writes are slow.
+    pub is_ddcci: bool,
+    /// The driver reports the kind of backlight interface (`type`).
+    pub has_type_attr: bool,
+    /// The brightness can likely be changed, see [`crate::Calibright::can_write`].
+    pub writable: bool,
+}
+
+/// Options shared by all devices of a [`crate::Calibright`] instance.
+#[derive(Clone, SmartDefault)]
+pub struct DeviceOptions {
+    /// Compute the raw brightness but skip writing it.
+    pub dry_run: bool,
+    /// Only read the brightness, without connecting to D-Bus. Writes fail.
+    pub read_only: bool,
+    /// Number of perceptually even steps between the lowest and highest brightness.
+    #[default(20)]
+    pub step_count: u32,
+    /// Return D-Bus errors instead of falling back to writing to sysfs.
+    pub require_logind: bool,
+    /// How to handle requested brightness values outside of 0.0 to 1.0.
+    pub overflow_policy: OverflowPolicy,
+    /// How a device that can't be read is counted in the combined brightness.
+    pub read_error_policy: ReadErrorPolicy,
+    /// Allow a raw brightness of 0, which turns off many backlights, instead of 1.
+    pub allow_zero: bool,
+    /// Skip writes of raw values within this distance of the cached raw brightness.
+    pub skip_write_tolerance: Option<u32>,
+    /// File the brightness is read from instead of the default of the subsystem/driver.
+    pub read_file: Option<OsString>,
+    /// File the brightness is written to if no `write_file` is configured for the device.
+    pub write_file: Option<OsString>,
+    /// File the max brightness is read from instead of `max_brightness`.
+    pub max_file: Option<OsString>,
+    /// Base delay between DDC/CI reads instead of the `ddcci_read_base_ms` of the device config.
+    pub ddc_base_delay: Option<Duration>,
+    /// File system used to discover, read and write the devices.
+    #[default(Arc::new(TokioFs))]
+    pub fs: Arc<dyn FsProvider>,
+    /// Connection used to reach logind instead of connecting to the system bus.
+    pub dbus_connection: Option<Connection>,
+    /// The D-Bus names used to reach logind.
+    pub logind_names: LogindNames,
+    #[cfg(feature = "helper")]
+    /// Command used to write the brightness when both D-Bus and sysfs writes fail.
+    pub privileged_helper: Option<Vec<OsString>>,
+}
+
+impl DeviceOptions {
+    /// File the max brightness is read from.
+    pub fn max_file(&self) -> &OsStr {
+        self.max_file
+            .as_deref()
+            .unwrap_or(FILE_MAX_BRIGHTNESS.as_ref())
+    }
+}
+
+/// The state of a device that changes as it is read and written, shared with the clones of the
+/// device (e.g. of the background refresh and coalesced writes) so that the cache stays current
+/// whichever clone reads or writes the device.
+#[derive(Debug)]
+struct SharedState {
+    raw_brightness: u32,
+    updated_at: Instant,
+    last_write_method: Option<WriteMethod>,
+    #[cfg(feature = "watch")]
+    last_observed: Option<(u32, Instant)>,
 }
 
 /// Represents a physical backlight device whose brightness level can be queried.
 #[derive(Clone)]
 pub struct Device {
     pub device_name: OsString,
+    pub subsystem: Subsystem,
     pub read_brightness_file: PathBuf,
+    pub hw_changed_file: Option<PathBuf>,
+    /// Name of the DRM connector (e.g. `eDP-1` or `DP-2`) the device belongs to, if known.
+    pub connector: Option<String>,
     write_brightness_file: PathBuf,
-    raw_brightness: u32,
+    max_brightness_file: PathBuf,
     max_brightness: u32,
-    dbus_proxy: SessionProxy<'static>,
+    /// Lowest usable raw brightness reported by the driver, if any.
+    brightness_min: Option<u32>,
+    scale: Option<BacklightScale>,
+    dbus_proxy: Option<SessionProxy<'static>>,
     config: DeviceConfig,
-    #[cfg(feature = "watch")]
-    updated_at: Instant,
+    options: DeviceOptions,
+    can_write: bool,
+    capabilities: Capabilities,
+    state: Arc<Mutex<SharedState>>,
+    /// Retries needed by the last read, shared with clones (e.g. of the background refresh).
+    last_read_retries: Arc<AtomicU32>,
 }
 
 impl Device {
-    pub async fn new(device_name: &String, config: DeviceConfig) -> Result<Self> {
-        let device_path = PathBuf::from(DEVICES_PATH).join(device_name);
+    pub async fn new(
+        subsystem: Subsystem,
+        device_name: &String,
+        config: &CalibrightConfig,
+        options: DeviceOptions,
+    ) -> Result<Self> {
+        let device_path = PathBuf::from(subsystem.path()).join(device_name);
+        let connector = connector_name(
+            &*options.fs,
+            &Path::new(subsystem.sysfs_path()).join(device_name),
+        )
+        .await;
+        let config = config.get_device_config(device_name, connector.as_deref());
 
-        let dbus_conn = Connection::system().await?;
+        let dbus_conn = match &options.dbus_connection {
+            _ if options.read_only => Err(zbus::Error::Unsupported),
+            #[cfg(feature = "i2c")]
+            _ if subsystem == Subsystem::I2c => Err(zbus::Error::Unsupported),
+            Some(dbus_conn) => Ok(dbus_conn.clone()),
+            None => Connection::system().await,
+        };
+        let dbus_proxy = match dbus_conn {
+            Ok(dbus_conn) => Some(
+                match session_proxy(&dbus_conn, &options.logind_names).await {
+                    Ok(dbus_proxy) => dbus_proxy,
+                    Err(e) if options.require_logind => {
+                        return Err(CalibrightError::LogindRequired(Box::new(e)))
+                    }
+                    Err(e) => {
+                        debug!("{e}, falling back to the /auto session path");
+                        auto_session_proxy(&dbus_conn, &options.logind_names).await?
+                    }
+                },
+            ),
+            Err(_) if options.read_only => None,
+            Err(e) if options.require_logind => {
+                return Err(CalibrightError::LogindRequired(Box::new(e.into())))
+            }
+            #[cfg(feature = "i2c")]
+            Err(_) if subsystem == Subsystem::I2c => None,
+            Err(e) => {
+                debug!("{e}, brightness will be written to sysfs directly");
+                None
+            }
+        };
 
         let mut s = Self {
             read_brightness_file: device_path.join({
-                if device_path
+                if let Some(read_file) = &options.read_file {
+                    read_file.as_os_str()
+                } else if subsystem == Subsystem::Leds {
+                    FILE_BRIGHTNESS_LEDS.as_ref()
+                } else if device_path
                     .file_name()
                     .and_then(OsStr::to_str)
-                    .map_or(false, |file_name| file_name.starts_with("amdgpu_bl"))
+                    .is_some_and(|file_name| file_name.starts_with("amdgpu_bl"))
                 {
-                    FILE_BRIGHTNESS_AMD
+                    FILE_BRIGHTNESS_AMD.as_ref()
                 } else {
-                    FILE_BRIGHTNESS
+                    FILE_BRIGHTNESS.as_ref()
                 }
             }),
-            write_brightness_file: device_path.join(FILE_BRIGHTNESS_WRITE),
+            hw_changed_file: None,
+            connector,
+            write_brightness_file: device_path.join(
+                config
+                    .write_file
+                    .as_deref()
+                    .or(options.write_file.as_deref().map(Path::new))
+                    .unwrap_or(Path::new(FILE_BRIGHTNESS_WRITE)),
+            ),
+            max_brightness_file: PathBuf::new(),
             device_name: device_name.into(),
-            raw_brightness: 0,
+            subsystem,
             max_brightness: 0,
-            dbus_proxy: SessionProxy::new(&dbus_conn).await?,
+            brightness_min: None,
+            scale: None,
+            dbus_proxy,
             config,
-            #[cfg(feature = "watch")]
-            updated_at: Instant::now(),
+            options,
+            can_write: false,
+            capabilities: Capabilities::default(),
+            state: Arc::new(Mutex::new(SharedState {
+                raw_brightness: 0,
+                updated_at: Instant::now(),
+                last_write_method: None,
+                #[cfg(feature = "watch")]
+                last_observed: None,
+            })),
+            last_read_retries: Arc::default(),
         };
-        s.raw_brightness = s.read_brightness_raw(&s.read_brightness_file).await?;
+        s.can_write = s.dbus_proxy.is_some()
+            || s.options
+                .fs
+                .can_write(&s.write_brightness_file)
+                .await
+                .unwrap_or(false);
+        #[cfg(feature = "helper")]
+        {
+            s.can_write |= s.options.privileged_helper.is_some();
+        }
+        #[cfg(feature = "i2c")]
+        if subsystem == Subsystem::I2c {
+            s.can_write = s.options.fs.can_write(&device_path).await.unwrap_or(false);
+        }
+        s.can_write &= !s.options.read_only;
+        s.state().raw_brightness = s.peek_raw().await?;
+        let max_file = match find_max_file(&device_path, &s.config, &s.options).await {
+            Some(max_file) => max_file,
+            // Reported as an error reading the default
+            None => device_path.join(s.options.max_file()),
+        };
+        // Some drivers report 0 until the device is fully set up after hotplug or resume
         s.max_brightness = s
-            .read_brightness_raw(&device_path.join(FILE_MAX_BRIGHTNESS))
+            .read_raw_retrying(&max_file, |max| max != 0, false)
             .await?;
+        s.max_brightness_file = max_file;
+        if s.max_brightness == 0 || s.max_brightness > MAX_BRIGHTNESS_LIMIT {
+            return Err(CalibrightError::Other(format!(
+                "{device_name} reports an unsupported max_brightness of {} (expected 1..={MAX_BRIGHTNESS_LIMIT})",
+                s.max_brightness
+            )));
+        }
+        // Only some drivers report a floor below which the panel isn't readable
+        s.brightness_min = s
+            .read_u32(&device_path.join(FILE_BRIGHTNESS_MIN))
+            .await
+            .ok()
+            .filter(|&min| min > 0 && min < s.max_brightness);
+        s.apply_brightness_min();
+        // Only devices whose brightness can be changed by the hardware have this file
+        let hw_changed_file = device_path.join(FILE_BRIGHTNESS_HW_CHANGED);
+        if s.options.fs.exists(&hw_changed_file).await.unwrap_or(false) {
+            s.hw_changed_file = Some(hw_changed_file);
+        }
+        // Only some backlight drivers expose the scale
+        s.scale = s
+            .options
+            .fs
+            .read(&device_path.join(FILE_SCALE))
+            .await
+            .ok()
+            .map(|content| BacklightScale::parse(&String::from_utf8_lossy(&content)));
+        let fs = &s.options.fs;
+        s.capabilities = Capabilities {
+            can_power_off: fs
+                .exists(&device_path.join(FILE_BL_POWER))
+                .await
+                .unwrap_or(false),
+            is_ddcci: s.device_name.to_string_lossy().starts_with(DDCCI_PREFIX),
+            has_type_attr: fs
+                .exists(&device_path.join(FILE_TYPE))
+                .await
+                .unwrap_or(false),
+            writable: s.can_write,
+        };
+        #[cfg(feature = "i2c")]
+        {
+            s.capabilities.is_ddcci |= subsystem == Subsystem::I2c;
+        }
         Ok(s)
     }
 
+    /// Check that the files the brightness is read from and written to and the max brightness
+    /// file exist and can be accessed, e.g. to catch a misconfigured `write_file` or `max_file`.
+    pub async fn check_files(&self) -> Result<()> {
+        #[cfg(feature = "i2c")]
+        if self.subsystem == Subsystem::I2c {
+            // There are no files, the bus was already opened when the device was created
+            return Ok(());
+        }
+        let fs = &self.options.fs;
+        let path_error = |path: &Path, e| {
+            CalibrightError::Other(format!("Can't access {}: {e}", path.display()))
+        };
+        for path in [&self.read_brightness_file, &self.max_brightness_file] {
+            fs.read(path).await.map_err(|e| path_error(path, e))?;
+        }
+        let path = &self.write_brightness_file;
+        if !fs.exists(path).await.map_err(|e| path_error(path, e))? {
+            return Err(path_error(
+                path,
+                std::io::Error::from(std::io::ErrorKind::NotFound),
+            ));
+        }
+        if self.dbus_proxy.is_none()
+            && !self.options.read_only
+            && !fs.can_write(path).await.unwrap_or(false)
+        {
+            return Err(CalibrightError::Other(format!(
+                "{} isn't writable and logind isn't reachable",
+                path.display()
+            )));
+        }
+
+        Ok(())
+    }
+
     /// Read a brightness value from the given path.
     async fn read_brightness_raw(&self, device_file: &Path) -> Result<u32> {
-        let val = match read_file(device_file).await {
-            Ok(v) => Ok(v),
-            Err(_) => {
-                for i in 1..self.config.ddcci_max_tries_write_read {
-                    debug!("retry {i} reading brightness");
-                    // See https://glenwing.github.io/docs/VESA-DDCCI-1.1.pdf
-                    // Section 4.3 for timing explanation
-                    sleep(Duration::from_millis(
-                        (40.0 * self.config.ddcci_sleep_multiplier).round() as u64,
-                    ))
-                    .await;
-                    if let Ok(val) = read_file(device_file).await {
-                        return Ok(val.parse()?);
+        self.read_raw_retrying(device_file, |_| true, false).await
+    }
+
+    /// Read an integer from a small file such as a sysfs attribute.
+    async fn read_u32(&self, path: &Path) -> Result<u32> {
+        #[cfg(feature = "i2c")]
+        if self.subsystem == Subsystem::I2c {
+            return self.read_ddc(path).await;
+        }
+        parse_u32(&self.options.fs.read(path).await?)
+    }
+
+    #[cfg(feature = "i2c")]
+    /// Read what would be in a sysfs file of a backlight over DDC/CI: the max file has the max
+    /// brightness, the brightness files have the current brightness. There are no other files.
+    async fn read_ddc(&self, path: &Path) -> Result<u32> {
+        let is_max = path.file_name() == Some(self.options.max_file());
+        if !is_max && path != self.read_brightness_file && path != self.write_brightness_file {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} not found", path.display()),
+            )
+            .into());
+        }
+        let bus = Path::new(I2C_DEVICES_PATH).join(&self.device_name);
+        let (current, max) =
+            crate::ddc::get_vcp(&bus, crate::ddc::VCP_BRIGHTNESS, self.ddcci_delay()).await?;
+        Ok(u32::from(if is_max { max } else { current }))
+    }
+
+    #[cfg(feature = "watch")]
+    /// Whether changes of the brightness show up as changes of the brightness file.
+    pub fn is_watchable(&self) -> bool {
+        if !self.options.fs.can_watch() {
+            return false;
+        }
+        #[cfg(feature = "i2c")]
+        if self.subsystem == Subsystem::I2c {
+            return false;
+        }
+        true
+    }
+
+    /// Delay between ddcci reads, based on the ddcci settings.
+    fn ddcci_delay(&self) -> Duration {
+        let base_ms = self
+            .options
+            .ddc_base_delay
+            .map_or(self.config.ddcci_read_base_ms as f64, |delay| {
+                delay.as_millis() as f64
+            });
+        Duration::from_millis((base_ms * self.config.ddcci_sleep_multiplier).round() as u64)
+    }
+
+    /// Read a raw value from the given path, retrying failed reads and values that aren't
+    /// `valid` based on the ddcci settings. With `stop_on_empty`, an empty file isn't retried, e.g.
+    /// so that another file can be read without waiting for the retries.
+    async fn read_raw_retrying(
+        &self,
+        device_file: &Path,
+        valid: fn(u32) -> bool,
+        stop_on_empty: bool,
+    ) -> Result<u32> {
+        let start = Instant::now();
+        let max_tries = max(1, self.config.ddcci_max_tries_write_read);
+        let mut tries = max_tries;
+        let mut last_error = None;
+        for attempt in 1..=max_tries {
+            if attempt > 1 {
+                debug!("retry {} reading brightness", attempt - 1);
+                // See https://glenwing.github.io/docs/VESA-DDCCI-1.1.pdf
+                // Section 4.3 for timing explanation
+                sleep(self.ddcci_delay()).await;
+            }
+            match self.read_u32(device_file).await {
+                Ok(val) if !valid(val) => {
+                    last_error = Some(CalibrightError::Other(format!("unexpected value {val}")));
+                }
+                Ok(val) => {
+                    self.last_read_retries
+                        .store(u32::from(attempt - 1), Ordering::Relaxed);
+                    debug!(
+                        "read {} after {} retries in {:?}",
+                        device_file.display(),
+                        attempt - 1,
+                        start.elapsed()
+                    );
+                    return Ok(val);
+                }
+                Err(e) => {
+                    // Only checked when the file was read but didn't parse, so that other reads
+                    // aren't doubled
+                    let parse_error = !matches!(e, CalibrightError::Io(_));
+                    last_error = Some(e);
+                    if stop_on_empty && parse_error && self.is_empty(device_file).await {
+                        debug!("{} is empty", device_file.display());
+                        tries = attempt;
+                        break;
                     }
                 }
-                Err(CalibrightError::Other(
-                    "Failed to read brightness file, check your ddcci settings".into(),
-                ))
             }
+        }
+        self.last_read_retries
+            .store(u32::from(tries - 1), Ordering::Relaxed);
+        debug!(
+            "failed to read {} after {} retries in {:?}",
+            device_file.display(),
+            tries - 1,
+            start.elapsed()
+        );
+        Err(self.error_context(CalibrightError::Other(format!(
+            "Failed to read brightness file {}, check your ddcci settings: {}",
+            device_file.display(),
+            last_error.map_or_else(String::new, |e| e.to_string())
+        ))))
+    }
+
+    /// Whether the file at `path` can be read and is empty (or only whitespace).
+    async fn is_empty(&self, path: &Path) -> bool {
+        self.options
+            .fs
+            .read(path)
+            .await
+            .is_ok_and(|contents| contents.trim_ascii().is_empty())
+    }
+
+    /// Read the current raw brightness without updating the cached value (or anything else the
+    /// watch logic compares against).
+    ///
+    /// If `actual_brightness` can't be read (e.g. it is empty during a driver transition), the
+    /// last requested brightness is read from `brightness` instead. The other way around, for
+    /// amdgpu backlights that are read from `brightness`, `actual_brightness` is read instead.
+    /// An empty read file isn't retried.
+    pub async fn peek_raw(&self) -> Result<u32> {
+        let fallback_file = match self.read_brightness_file.file_name() {
+            Some(file_name) if file_name == FILE_BRIGHTNESS => FILE_BRIGHTNESS_WRITE,
+            Some(file_name)
+                if file_name == FILE_BRIGHTNESS_AMD && self.subsystem == Subsystem::Backlight =>
+            {
+                FILE_BRIGHTNESS
+            }
+            _ => return self.read_brightness_raw(&self.read_brightness_file).await,
+        };
+        let error = match self
+            .read_raw_retrying(&self.read_brightness_file, |_| true, true)
+            .await
+        {
+            Ok(raw_brightness) => return Ok(raw_brightness),
+            Err(e) => e,
         };
-        Ok(val?.parse()?)
+        let fallback_file = self.read_brightness_file.with_file_name(fallback_file);
+        match self.read_u32(&fallback_file).await {
+            Ok(raw_brightness) => {
+                debug!("{error}, read {} instead", fallback_file.display());
+                Ok(raw_brightness)
+            }
+            Err(_) => Err(error),
+        }
+    }
+
+    /// Update the cached raw brightness with a value read from the device, applying
+    /// `read_hysteresis`.
+    pub fn update_raw_brightness(&self, raw_brightness: u32) {
+        let mut state = self.state();
+        if raw_brightness.abs_diff(state.raw_brightness) < self.config.read_hysteresis {
+            debug!(
+                "ignoring change from {} to {raw_brightness} within hysteresis",
+                state.raw_brightness
+            );
+        } else {
+            state.raw_brightness = raw_brightness;
+        }
+    }
+
+    /// Like [`Device::update_raw_brightness`], with a value read starting at `read_at`. The value
+    /// is ignored if the brightness was set since, so that a read made before a write doesn't
+    /// undo it.
+    pub(crate) fn update_raw_brightness_read_at(&self, raw_brightness: u32, read_at: Instant) {
+        if read_at >= self.state().updated_at {
+            self.update_raw_brightness(raw_brightness);
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, SharedState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
     }
 
     /// Query the brightness value for this backlight device, as a percent (0.0..=1.0).
     pub async fn get_brightness(&mut self) -> Result<f64> {
-        self.raw_brightness = self.read_brightness_raw(&self.read_brightness_file).await?;
+        let raw_brightness = self.peek_raw().await?;
+        self.update_raw_brightness(raw_brightness);
+        self.cached_brightness()
+    }
 
-        let brightness_ratio = (self.raw_brightness as f64 / self.max_brightness as f64)
-            .powf(self.config.root_scaling.recip());
+    /// The brightness value based on the cached raw brightness, as a percent (0.0..=1.0).
+    pub fn cached_brightness(&self) -> Result<f64> {
+        self.brightness_from_raw(self.invert_raw(self.cached_raw_brightness()))
+    }
+
+    /// The change in brightness, as a percent (0.0..=1.0), of one raw step from the cached raw
+    /// brightness. With `root_scaling` the size of a step depends on the current level.
+    pub fn min_step_percent(&self) -> Result<f64> {
+        let raw_brightness = self.invert_raw(self.cached_raw_brightness());
+        let (low, high) = if raw_brightness < self.max_brightness {
+            (raw_brightness, raw_brightness + 1)
+        } else {
+            (raw_brightness - 1, raw_brightness)
+        };
+        Ok(self.brightness_from_raw(high)? - self.brightness_from_raw(low)?)
+    }
+
+    /// The brightness value for a raw value (before inverting), as a percent (0.0..=1.0).
+    fn brightness_from_raw(&self, raw_brightness: u32) -> Result<f64> {
+        let hardware_ratio = raw_brightness as f64 / self.max_brightness as f64;
+        let brightness_ratio = ((hardware_ratio - self.config.offset) / (1.0 - self.config.offset))
+            .clamp(0.0, 1.0)
+            .powf((self.config.root_scaling * self.config.gamma).recip());
 
-        scale_to_clamped_absolute(
+        let brightness = scale_to_clamped_absolute(
             brightness_ratio,
             self.config.calibration[0],
             self.config.calibration[1],
-        )
+            self.config.calibration_snap,
+        )?;
+
+        Ok(match &self.config.curve_points {
+            Some(curve_points) => interpolate_curve_inverse(curve_points, brightness),
+            None => brightness,
+        })
     }
 
     /// Set the brightness value for this backlight device, as a percent (0.0..=1.0).
     pub async fn set_brightness(&mut self, value: f64) -> Result<()> {
-        let value = scale_to_clamped_relative(
+        let value = self.calibrate(value)?;
+        self.write_brightness(value).await
+    }
+
+    /// The brightness value, as a percent (0.0..=1.0), this device would end up at after
+    /// [`Device::set_brightness`] with `value`, i.e. after rounding to a raw value. Nothing is
+    /// written.
+    pub fn effective_brightness(&self, value: f64) -> Result<f64> {
+        let value = self.calibrate(value)?;
+        self.brightness_from_raw(self.raw_from_brightness(value))
+    }
+
+    /// The configured `calibration` window, as percents (0.0..=1.0) that `0.0` and `1.0` are
+    /// mapped to.
+    pub fn calibration_window(&self) -> [f64; 2] {
+        self.config.calibration
+    }
+
+    /// The hardware brightness, as a ratio of the raw value to the max raw value (0.0..=1.0),
+    /// that [`Device::set_brightness`] with `value` would write, e.g. to explain why `0.0`
+    /// doesn't turn the screen off. Nothing is written.
+    pub fn hardware_brightness(&self, value: f64) -> Result<f64> {
+        let value = self.calibrate(value)?;
+        Ok(self.raw_from_brightness(value) as f64 / self.max_brightness as f64)
+    }
+
+    /// Apply the overflow policy, the curve and the calibration window to a requested brightness.
+    fn calibrate(&self, value: f64) -> Result<f64> {
+        let mut value = self.options.overflow_policy.apply(value)?;
+        if let Some(curve_points) = &self.config.curve_points {
+            value = interpolate_curve(curve_points, value);
+        }
+        scale_to_clamped_relative(
             value,
             self.config.calibration[0],
             self.config.calibration[1],
-        )?;
-        let ratio = value.powf(self.config.root_scaling);
-        self.raw_brightness = max(1, (ratio * (self.max_brightness as f64)).round() as u32);
-        match self
-            .dbus_proxy
+        )
+    }
+
+    /// Query the brightness value for this backlight device, as a percent (0.0..=1.0), together
+    /// with the device's weight in the average brightness.
+    ///
+    /// Read errors are handled according to the [`ReadErrorPolicy`].
+    pub async fn get_weighted_brightness(&mut self) -> Result<(f64, f64)> {
+        let brightness = match (self.get_brightness().await, self.options.read_error_policy) {
+            (Ok(brightness), _) => brightness,
+            (Err(e), ReadErrorPolicy::Skip) => return Err(e),
+            (Err(e), ReadErrorPolicy::LastKnown) => {
+                debug!("{e}, using the last known brightness");
+                self.cached_brightness()?
+            }
+            (Err(e), ReadErrorPolicy::Zero) => {
+                debug!("{e}, counting the brightness as 0");
+                0.0
+            }
+        };
+        Ok((brightness, self.config.weight))
+    }
+
+    /// Adjust the brightness value for this backlight device relative to its current brightness.
+    pub async fn adjust_brightness(&mut self, delta: f64) -> Result<()> {
+        let brightness = self.get_brightness().await?;
+        self.set_brightness(brightness + delta).await
+    }
+
+    /// Move the brightness by `steps` perceptually even steps (negative to decrease).
+    ///
+    /// Steps are taken based on the calibration settings, so with `root_scaling` they are even in
+    /// the curved domain rather than in raw values. The brightness snaps to the nearest step.
+    pub async fn step_brightness(&mut self, steps: i32) -> Result<()> {
+        let step_count = max(1, self.options.step_count) as f64;
+        let brightness = self.get_brightness().await?;
+        let step = (brightness * step_count).round() + steps as f64;
+        // Stepping past either end stops there, regardless of the overflow policy
+        self.set_brightness((step / step_count).clamp(0.0, 1.0))
+            .await
+    }
+
+    /// Move the raw brightness by `steps` raw values (negative to decrease), ignoring all of the
+    /// calibration settings. The raw value is clamped to `1..=max_brightness`
+    /// (`0..=max_brightness` with [`DeviceOptions::allow_zero`]).
+    pub async fn adjust_raw(&mut self, steps: i32) -> Result<()> {
+        let raw_brightness = self.peek_raw().await?;
+        self.update_raw_brightness(raw_brightness);
+        let raw_brightness = (i64::from(self.invert_raw(raw_brightness)) + i64::from(steps))
+            .clamp(i64::from(self.min_raw()), i64::from(self.max_brightness));
+        self.write_brightness_raw(self.invert_raw(raw_brightness as u32))
+            .await
+    }
+
+    /// Set the brightness value for this backlight device, as a percent (0.0..=1.0) of the full
+    /// hardware range.
+    ///
+    /// Unlike [`Device::set_brightness`], the calibration window is ignored, so `1.0` always maps
+    /// to `max_brightness`. `root_scaling` and the [`OverflowPolicy`] are still applied.
+    pub async fn set_brightness_uncalibrated(&mut self, value: f64) -> Result<()> {
+        let value = self.options.overflow_policy.apply(value)?;
+        self.write_brightness(value).await
+    }
+
+    /// Apply `root_scaling`, `gamma` and `offset` to an already calibrated value and write it to
+    /// the device.
+    async fn write_brightness(&mut self, value: f64) -> Result<()> {
+        let raw_brightness = self.raw_from_brightness(value);
+        self.write_brightness_raw(self.invert_raw(raw_brightness))
+            .await
+    }
+
+    /// Apply `root_scaling`, `gamma` and `offset` to an already calibrated value, returning the
+    /// raw value (before inverting).
+    fn raw_from_brightness(&self, value: f64) -> u32 {
+        let ratio = self.config.offset
+            + value.powf(self.config.root_scaling * self.config.gamma) * (1.0 - self.config.offset);
+        let max_brightness = self.max_brightness as f64;
+        // Saturate rather than relying on the float to integer cast for out of range values
+        self.cap_raw(
+            (ratio * max_brightness)
+                .round()
+                .clamp(f64::from(self.min_raw()), max_brightness) as u32,
+        )
+    }
+
+    /// The lowest raw value (before inverting) that is written, see [`DeviceOptions::allow_zero`].
+    fn min_raw(&self) -> u32 {
+        if self.options.allow_zero {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Limit a raw value (before inverting) to the `max_brightness_cap`, if configured.
+    fn cap_raw(&self, raw_brightness: u32) -> u32 {
+        match self.config.max_brightness_cap {
+            Some(cap) => {
+                let max_raw = max(1, (cap * self.max_brightness as f64).round() as u32);
+                raw_brightness.min(max_raw)
+            }
+            None => raw_brightness,
+        }
+    }
+
+    /// Flip a raw value if the device is configured as inverted. This converts both ways.
+    fn invert_raw(&self, raw_brightness: u32) -> u32 {
+        if self.config.invert {
+            self.max_brightness.saturating_sub(raw_brightness)
+        } else {
+            raw_brightness
+        }
+    }
+
+    /// Query the luminance of this device in nits, based on the configured `max_nits`.
+    ///
+    /// The luminance is assumed to be linear in the raw brightness, the calibration settings
+    /// don't apply.
+    pub async fn get_brightness_nits(&mut self) -> Result<f64> {
+        let max_nits = self.max_nits()?;
+        let raw_brightness = self.peek_raw().await?;
+        self.update_raw_brightness(raw_brightness);
+        Ok(
+            self.invert_raw(self.cached_raw_brightness()) as f64 / self.max_brightness as f64
+                * max_nits,
+        )
+    }
+
+    /// Set the luminance of this device in nits, based on the configured `max_nits`. Values
+    /// outside of what the device supports are clamped.
+    ///
+    /// The luminance is assumed to be linear in the raw brightness, the calibration settings
+    /// don't apply. NaN and infinity are rejected with [`CalibrightError::InvalidBrightness`].
+    pub async fn set_brightness_nits(&mut self, nits: f64) -> Result<()> {
+        if !nits.is_finite() {
+            return Err(CalibrightError::InvalidBrightness(nits));
+        }
+        let max_nits = self.max_nits()?;
+        let max_brightness = self.max_brightness as f64;
+        let raw_brightness = (nits / max_nits * max_brightness)
+            .round()
+            .clamp(f64::from(self.min_raw()), max_brightness) as u32;
+        self.write_brightness_raw(self.invert_raw(raw_brightness))
+            .await
+    }
+
+    fn max_nits(&self) -> Result<f64> {
+        self.config.max_nits.ok_or_else(|| {
+            CalibrightError::Other(format!("No max_nits configured for {:?}", self.device_name))
+        })
+    }
+
+    /// Set a raw brightness value as read by [`Device::peek_raw`], ignoring all of the
+    /// calibration settings.
+    pub async fn set_brightness_raw(&mut self, raw_brightness: u32) -> Result<()> {
+        self.write_brightness_raw(raw_brightness).await
+    }
+
+    /// Set the brightness to a fraction (0.0..=1.0) of the raw hardware range, ignoring all of the
+    /// calibration settings (including `root_scaling`). The raw value is clamped to
+    /// `1..=max_brightness` (`0..=max_brightness` with [`DeviceOptions::allow_zero`]).
+    pub async fn set_hardware_percent(&mut self, percent: f64) -> Result<()> {
+        if percent.is_nan() {
+            return Err(CalibrightError::InvalidBrightness(percent));
+        }
+        let max_brightness = self.max_brightness as f64;
+        let raw_brightness = (percent * max_brightness)
+            .round()
+            .clamp(f64::from(self.min_raw()), max_brightness) as u32;
+        self.write_brightness_raw(self.invert_raw(raw_brightness))
+            .await
+    }
+
+    /// Set the brightness to its hardware max, ignoring all of the calibration settings except
+    /// for `max_brightness_cap`.
+    pub async fn maximize(&mut self) -> Result<()> {
+        self.write_brightness_raw(self.invert_raw(self.max_brightness))
+            .await
+    }
+
+    /// Set the brightness to its lowest raw value, ignoring all of the calibration settings. This
+    /// is `1`, which doesn't turn off the backlight, unless [`DeviceOptions::allow_zero`] is set.
+    pub async fn minimize(&mut self) -> Result<()> {
+        self.write_brightness_raw(self.invert_raw(self.min_raw()))
+            .await
+    }
+
+    /// Write a raw brightness value to the device, naming the device in errors.
+    ///
+    /// The value is limited to the `max_brightness_cap`, whichever way it was computed.
+    async fn write_brightness_raw(&mut self, raw_brightness: u32) -> Result<()> {
+        let raw_brightness = self.invert_raw(self.cap_raw(self.invert_raw(raw_brightness)));
+        self.try_write_brightness_raw(raw_brightness)
+            .await
+            .map_err(|e| self.error_context(e))
+    }
+
+    /// Attach the name of this device to `error`, so it is clear which of several devices failed.
+    fn error_context(&self, error: CalibrightError) -> CalibrightError {
+        CalibrightError::Device {
+            name: self.device_name.clone(),
+            source: Box::new(error),
+        }
+    }
+
+    /// Write a raw brightness value to the device.
+    async fn try_write_brightness_raw(&mut self, raw_brightness: u32) -> Result<()> {
+        if self.options.read_only {
+            return Err(CalibrightError::ReadOnly);
+        }
+        if let Some(tolerance) = self.options.skip_write_tolerance {
+            let cached_raw_brightness = self.cached_raw_brightness();
+            if raw_brightness.abs_diff(cached_raw_brightness) <= tolerance {
+                debug!(
+                    "skipping write of {raw_brightness} to {:?}, already at {cached_raw_brightness}",
+                    self.device_name
+                );
+                return Ok(());
+            }
+        }
+        self.state().raw_brightness = raw_brightness;
+        if self.options.dry_run {
+            debug!(
+                "dry run: would set {:?} to {raw_brightness}",
+                self.device_name
+            );
+            return Ok(());
+        }
+        let Some(dbus_proxy) = &self.dbus_proxy else {
+            // No system bus available
+            return self
+                .write_fallback()
+                .await
+                .map(|method| self.mark_updated(method));
+        };
+        let start = Instant::now();
+        match dbus_proxy
             .set_brightness(
-                "backlight",
+                self.subsystem.as_str(),
                 &self.device_name.to_string_lossy(),
-                self.raw_brightness,
+                raw_brightness,
             )
             .await
         {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                debug!(
+                    "set {:?} via D-Bus in {:?}",
+                    self.device_name,
+                    start.elapsed()
+                );
+                self.confirm_raw_brightness().await;
+                Ok(WriteMethod::Logind)
+            }
             Err(e) => {
-                debug!("{}", e.to_string());
-                // Fall back to writing to sysfs brightness file
-                let mut file = OpenOptions::new()
-                    .write(true)
-                    .truncate(true)
-                    .open(&self.write_brightness_file)
-                    .await?;
-                Ok(file
-                    .write_all(self.raw_brightness.to_string().as_bytes())
-                    .await?)
+                debug!(
+                    "D-Bus failed for {:?} in {:?}: {}",
+                    self.device_name,
+                    start.elapsed(),
+                    e
+                );
+                if self.options.require_logind {
+                    return Err(CalibrightError::LogindRequired(Box::new(e.into())));
+                }
+                if !self.sysfs_fallback() {
+                    return Err(e.into());
+                }
+                // Keep the D-Bus error, as it is usually the reason the fallback was needed
+                self.write_fallback()
+                    .await
+                    .map_err(|fallback| CalibrightError::SetBrightness {
+                        dbus: e,
+                        fallback: Box::new(fallback),
+                    })
             }
         }
-        .map(|_| {
-            #[cfg(feature = "watch")]
-            {
-                self.updated_at = Instant::now();
+        .map(|method| self.mark_updated(method))
+    }
+
+    /// Read back the raw brightness after a write. The driver may not take the value exactly
+    /// (e.g. rounding to its own steps), so this keeps the cache in line with the hardware.
+    async fn confirm_raw_brightness(&self) {
+        match self.read_u32(&self.read_brightness_file).await {
+            Ok(raw_brightness) => self.state().raw_brightness = raw_brightness,
+            Err(e) => {
+                debug!("{e}, assuming {} was set", self.cached_raw_brightness());
             }
+        }
+    }
+
+    /// Whether to fall back to writing sysfs when writing via logind fails, see
+    /// [`DeviceConfig::sysfs_fallback`].
+    fn sysfs_fallback(&self) -> bool {
+        self.config
+            .sysfs_fallback
+            .unwrap_or_else(|| !self.device_name.to_string_lossy().starts_with(DDCCI_PREFIX))
+    }
+
+    /// Treat the brightness as just set, while the write is done by another task, so that it
+    /// isn't mistaken for an external change.
+    pub(crate) fn mark_pending_write(&mut self) {
+        self.state().updated_at = Instant::now();
+    }
+
+    fn mark_updated(&mut self, method: WriteMethod) {
+        let mut state = self.state();
+        state.last_write_method = Some(method);
+        state.updated_at = Instant::now();
+        // When the read and write files are the same (amdgpu), the write shows up as a
+        // change of the watched file, so treat it as already observed.
+        #[cfg(feature = "watch")]
+        {
+            state.last_observed = Some((state.raw_brightness, state.updated_at));
+        }
+    }
+
+    /// Write the current raw brightness without D-Bus, i.e. to sysfs, or using the privileged
+    /// helper if that fails.
+    async fn write_fallback(&self) -> Result<WriteMethod> {
+        #[cfg(feature = "i2c")]
+        if self.subsystem == Subsystem::I2c {
+            return self.write_ddc().await.map(|_| WriteMethod::Ddc);
+        }
+        let res = self.write_sysfs().await.map(|_| WriteMethod::Sysfs);
+        #[cfg(feature = "helper")]
+        let res = match (res, &self.options.privileged_helper) {
+            (Err(sysfs_error), Some(helper)) => {
+                debug!("{sysfs_error}, falling back to the privileged helper");
+                self.write_privileged_helper(helper)
+                    .await
+                    .map(|_| WriteMethod::PrivilegedHelper)
+            }
+            (res, _) => res,
+        };
+        res
+    }
+
+    #[cfg(feature = "i2c")]
+    /// Write the current raw brightness over DDC/CI.
+    async fn write_ddc(&self) -> Result<()> {
+        let start = Instant::now();
+        let bus = Path::new(I2C_DEVICES_PATH).join(&self.device_name);
+        let raw_brightness = self.cached_raw_brightness();
+        let value = u16::try_from(raw_brightness).map_err(|_| {
+            CalibrightError::Other(format!("Invalid DDC/CI value {raw_brightness}"))
+        })?;
+        crate::ddc::set_vcp(&bus, crate::ddc::VCP_BRIGHTNESS, value).await?;
+        debug!(
+            "set {:?} via DDC/CI in {:?}",
+            self.device_name,
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    /// Write the current raw brightness to the sysfs brightness file.
+    async fn write_sysfs(&self) -> Result<()> {
+        let start = Instant::now();
+        self.options
+            .fs
+            .write(
+                &self.write_brightness_file,
+                self.cached_raw_brightness().to_string().as_bytes(),
+            )
+            .await
+            .map_err(|e| match e.kind() {
+                // E.g. the device was rebound and lost the permissions granted by udev
+                std::io::ErrorKind::PermissionDenied => CalibrightError::PermissionDenied {
+                    path: self.write_brightness_file.clone(),
+                    source: e,
+                },
+                _ => e.into(),
+            })?;
+        debug!(
+            "set {:?} via {} in {:?}",
+            self.device_name,
+            self.write_brightness_file.display(),
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "helper")]
+    /// Write the current raw brightness by running the privileged helper command with the
+    /// subsystem, device name and raw brightness appended as arguments.
+    async fn write_privileged_helper(&self, helper: &[OsString]) -> Result<()> {
+        let Some((program, args)) = helper.split_first() else {
+            return Err(CalibrightError::Other(
+                "Privileged helper command is empty".into(),
+            ));
+        };
+        let start = Instant::now();
+        let status = tokio::process::Command::new(program)
+            .args(args)
+            .arg(self.subsystem.as_str())
+            .arg(&self.device_name)
+            .arg(self.cached_raw_brightness().to_string())
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(CalibrightError::Other(format!(
+                "Privileged helper {} failed: {status}",
+                program.to_string_lossy()
+            )));
+        }
+        debug!(
+            "set {:?} via privileged helper in {:?}",
+            self.device_name,
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    /// Read the brightness once and package it together with the device's properties.
+    pub async fn snapshot(&mut self) -> Result<DeviceSnapshot> {
+        let percent = self.get_brightness().await?;
+        Ok(DeviceSnapshot {
+            name: self.device_name.clone(),
+            subsystem: self.subsystem,
+            raw: self.cached_raw_brightness(),
+            max: self.max_brightness,
+            scale: self.scale,
+            percent,
+            config: self.config.clone(),
         })
     }
 
-    #[cfg(feature = "watch")]
+    /// The raw brightness last read from or written to the device. In dry-run mode this is the
+    /// value that would have been written.
+    pub fn cached_raw_brightness(&self) -> u32 {
+        self.state().raw_brightness
+    }
+
+    /// What this device supports.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// The scale reported by the driver, if it exposes one.
+    pub fn scale(&self) -> Option<BacklightScale> {
+        self.scale
+    }
+
+    /// The maximum raw brightness value supported by the device.
+    pub fn max_brightness(&self) -> u32 {
+        self.max_brightness
+    }
+
+    /// Whether the brightness can likely be changed, i.e. logind is reachable over D-Bus or the
+    /// sysfs brightness file was writable when the device was discovered.
+    pub fn can_write(&self) -> bool {
+        self.can_write
+    }
+
+    /// The path of the logind session used to set the brightness, or `None` if logind isn't
+    /// reachable and sysfs is written directly.
+    pub fn session_path(&self) -> Option<String> {
+        self.dbus_proxy
+            .as_ref()
+            .map(|dbus_proxy| dbus_proxy.inner().path().to_string())
+    }
+
+    /// The human readable name of this device, if configured.
+    pub fn alias(&self) -> Option<&str> {
+        self.config.alias.as_deref()
+    }
+
+    /// The resolved configuration used by this device.
+    pub fn config(&self) -> &DeviceConfig {
+        &self.config
+    }
+
+    /// Replace the configuration used by this device.
+    pub fn set_config(&mut self, config: DeviceConfig) {
+        self.config = config;
+        self.apply_brightness_min();
+    }
+
+    /// Raise the lower bound of the calibration window to the floor reported by the driver, unless
+    /// a calibration window is configured (see [`DeviceConfig::calibration_set`]) or, for a
+    /// [`DeviceConfig`] built in code, differs from the default.
+    fn apply_brightness_min(&mut self) {
+        let Some(brightness_min) = self.brightness_min else {
+            return;
+        };
+        if self.config.calibration_set
+            || self.config.calibration != DeviceConfig::default().calibration
+        {
+            return;
+        }
+        let hardware_ratio = brightness_min as f64 / self.max_brightness as f64;
+        self.config.calibration[0] = ((hardware_ratio - self.config.offset)
+            / (1.0 - self.config.offset))
+            .clamp(0.0, 1.0)
+            .powf((self.config.root_scaling * self.config.gamma).recip());
+        debug!(
+            "using the driver's brightness_min {brightness_min} as calibration {:?}",
+            self.config.calibration
+        );
+    }
+
+    /// How the brightness was last written, or `None` if it was never written (or only in
+    /// dry-run mode).
+    pub fn last_write_method(&self) -> Option<WriteMethod> {
+        self.state().last_write_method
+    }
+
+    /// How many times the last read had to be retried, e.g. to tune `ddcci_max_tries_write_read`
+    /// and `ddcci_sleep_multiplier` for a flaky DDC/CI monitor.
+    pub fn last_read_retries(&self) -> u32 {
+        self.last_read_retries.load(Ordering::Relaxed)
+    }
+
+    /// When the brightness was last set, or when the device was discovered if it was never set.
+    pub fn get_last_set_at(&self) -> std::time::Instant {
+        self.state().updated_at.into_std()
+    }
+
+    /// How long ago the brightness was last set, or when the device was discovered if it was
+    /// never set.
     pub fn get_last_set_ago(&self) -> Duration {
-        self.updated_at.elapsed()
+        self.state().updated_at.elapsed()
+    }
+
+    #[cfg(feature = "watch")]
+    /// Record an externally observed change of the brightness file, updating the cached raw
+    /// brightness so that e.g. [`Device::cached_brightness`] reflects the change.
+    ///
+    /// Returns `false` if the same raw value was already observed within `window`, so that
+    /// duplicate events for a single change can be ignored.
+    pub async fn observe_change(&mut self, window: Duration) -> bool {
+        let Ok(raw_brightness) = self.read_u32(&self.read_brightness_file).await else {
+            // Can't tell whether this is a duplicate, so report it
+            return true;
+        };
+        {
+            let mut state = self.state();
+            if let Some((last_raw, observed_at)) = state.last_observed {
+                if last_raw == raw_brightness && observed_at.elapsed() < window {
+                    debug!("duplicate change to {raw_brightness} ignored");
+                    return false;
+                }
+            }
+            state.last_observed = Some((raw_brightness, Instant::now()));
+        }
+        self.update_raw_brightness(raw_brightness);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fs::tests::*;
+    use crate::fs::MemoryFs;
+
+    const NAME: &str = "calibright_test";
+
+    /// The device [`NAME`] of `fs`, with `toml` as the contents of its config section.
+    async fn device_in<T: FsProvider + 'static>(fs: &Arc<T>, toml: &str) -> Result<Device> {
+        let config = config(&format!("[{NAME}]\n{toml}"));
+        Device::new(
+            Subsystem::Backlight,
+            &NAME.to_string(),
+            &config,
+            device_options(fs),
+        )
+        .await
+    }
+
+    /// The device [`NAME`] of `fs` with a default config and the given options.
+    async fn device_with(options: DeviceOptions) -> Result<Device> {
+        Device::new(Subsystem::Backlight, &NAME.into(), &config(""), options).await
+    }
+
+    /// A backlight at `raw` of `max`, with `toml` as the contents of its config section.
+    async fn test_device(raw: u32, max: u32, toml: &str) -> (Arc<MemoryFs>, Device) {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, raw, max);
+        let device = device_in(&fs, toml).await.unwrap();
+        (fs, device)
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[tokio::test]
+    async fn minimize_writes_the_lowest_raw_value() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        let (fs, mut device) = test_device(50, 100, "").await;
+        device.minimize().await.unwrap();
+        assert_eq!(read_number(&fs, &written), 1);
+
+        let (fs, mut device) = test_device(50, 100, "invert = true").await;
+        device.minimize().await.unwrap();
+        assert_eq!(read_number(&fs, &written), 99);
+
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        let options = DeviceOptions {
+            allow_zero: true,
+            ..device_options(&fs)
+        };
+        let mut device = device_with(options).await.unwrap();
+        device.minimize().await.unwrap();
+        assert_eq!(read_number(&fs, &written), 0);
+    }
+
+    #[tokio::test]
+    async fn values_rounding_to_zero_need_allow_zero() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        for (allow_zero, expected) in [(false, 1), (true, 0)] {
+            let fs = Arc::new(MemoryFs::new());
+            insert_backlight(&fs, NAME, 50, 100);
+            let options = DeviceOptions {
+                allow_zero,
+                ..device_options(&fs)
+            };
+            let mut device = device_with(options).await.unwrap();
+            device.set_brightness(0.004).await.unwrap();
+            assert_eq!(read_number(&fs, &written), expected);
+            device.set_brightness(0.0).await.unwrap();
+            assert_eq!(read_number(&fs, &written), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn gamma_and_offset_map_to_raw() {
+        let (_, device) = test_device(1, 1000, "gamma = 2.0\noffset = 50.0").await;
+        // The offset is the lowest hardware ratio, gamma curves the rest of the range
+        assert_eq!(device.raw_from_brightness(0.0), 500);
+        assert_eq!(device.raw_from_brightness(0.5), 625);
+        assert_eq!(device.raw_from_brightness(1.0), 1000);
+        assert_eq!(device.brightness_from_raw(625).unwrap(), 0.5);
+        // Raw values below the offset are unusable and read as 0
+        assert_eq!(device.brightness_from_raw(100).unwrap(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn gamma_offset_and_calibration_round_trip() {
+        let (_, device) = test_device(
+            1,
+            65535,
+            "gamma = 2.2\noffset = 5.0\nroot_scaling = 1.5\ncalibration = [10.0, 90.0]",
+        )
+        .await;
+        for step in 0..=20 {
+            let value = step as f64 / 20.0;
+            let raw = device.raw_from_brightness(device.calibrate(value).unwrap());
+            assert_close(device.brightness_from_raw(raw).unwrap(), value, 1e-3);
+        }
+        // The calibration window limits the hardware range
+        assert!(device.hardware_brightness(0.0).unwrap() > 0.05);
+        assert!(device.hardware_brightness(1.0).unwrap() < 1.0);
+    }
+
+    #[tokio::test]
+    async fn max_raw_brightness_reads_as_exactly_one() {
+        let (_, mut device) = test_device(937, 937, "gamma = 2.2\noffset = 5.0").await;
+        assert_eq!(device.get_brightness().await.unwrap(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn max_brightness_cap_limits_every_write() {
+        let (fs, mut device) = test_device(50, 100, "max_brightness_cap = 70.0").await;
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+
+        device.set_brightness(1.0).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 70);
+        device.maximize().await.unwrap();
+        assert_eq!(read_number(&fs, &written), 70);
+        device.set_brightness(0.5).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 50);
+    }
+
+    #[tokio::test]
+    async fn peek_raw_leaves_the_cache_unchanged() {
+        let (fs, device) = test_device(50, 100, "").await;
+        let updated_at = device.get_last_set_at();
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "80");
+
+        assert_eq!(device.peek_raw().await.unwrap(), 80);
+        assert_eq!(device.cached_raw_brightness(), 50);
+        assert_eq!(device.get_last_set_at(), updated_at);
+    }
+
+    #[tokio::test]
+    async fn reads_are_attempted_exactly_max_tries_times() {
+        let read_file = backlight_file(NAME, FILE_BRIGHTNESS);
+        for max_tries in [1u8, 3, 5] {
+            let fs = Arc::new(FlakyFs::default());
+            insert_backlight(&fs.fs, NAME, 50, 100);
+            let toml = format!("ddcci_max_tries_write_read = {max_tries}\nddcci_read_base_ms = 1");
+            let device = device_in(&fs, &toml).await.unwrap();
+            let reads = fs.reads(&read_file);
+
+            fs.fail_reads(&read_file, u32::MAX);
+            assert!(device.read_brightness_raw(&read_file).await.is_err());
+            assert_eq!(fs.reads(&read_file) - reads, u32::from(max_tries));
+            assert_eq!(device.last_read_retries(), u32::from(max_tries - 1));
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_stop_at_the_first_success() {
+        let read_file = backlight_file(NAME, FILE_BRIGHTNESS);
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 50, 100);
+        let toml = "ddcci_max_tries_write_read = 5\nddcci_read_base_ms = 1";
+        let device = device_in(&fs, toml).await.unwrap();
+        let reads = fs.reads(&read_file);
+
+        fs.fail_reads(&read_file, 2);
+        assert_eq!(device.read_brightness_raw(&read_file).await.unwrap(), 50);
+        assert_eq!(fs.reads(&read_file) - reads, 3);
+        assert_eq!(device.last_read_retries(), 2);
+    }
+
+    #[tokio::test]
+    async fn require_logind_doesnt_fall_back_to_sysfs() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+
+        // Without logind the brightness is written to sysfs
+        let mut device = device_with(device_options(&fs)).await.unwrap();
+        device.set_brightness(0.8).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 80);
+
+        let options = DeviceOptions {
+            require_logind: true,
+            ..device_options(&fs)
+        };
+        let result = device_with(options).await;
+        assert!(matches!(result, Err(CalibrightError::LogindRequired(_))));
+        assert_eq!(read_number(&fs, &written), 80);
+    }
+
+    #[tokio::test]
+    async fn max_brightness_out_of_range_is_rejected() {
+        let toml = "ddcci_read_base_ms = 1";
+        for max in [0, MAX_BRIGHTNESS_LIMIT + 1, u32::MAX] {
+            let fs = Arc::new(MemoryFs::new());
+            insert_backlight(&fs, NAME, 1, max);
+            assert!(device_in(&fs, toml).await.is_err(), "{max} was accepted");
+        }
+
+        let (fs, mut device) = test_device(1, MAX_BRIGHTNESS_LIMIT, toml).await;
+        device.set_brightness(1.0).await.unwrap();
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        assert_eq!(read_number(&fs, &written), MAX_BRIGHTNESS_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn overflow_policy_applies_at_the_boundaries() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 100, 100);
+
+        let mut device = device_with(device_options(&fs)).await.unwrap();
+        device.set_brightness(1.5).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 100);
+        device.adjust_brightness(0.1).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 100);
+        device.set_brightness(-0.5).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 1);
+
+        let options = DeviceOptions {
+            overflow_policy: OverflowPolicy::Error,
+            ..device_options(&fs)
+        };
+        let mut device = device_with(options).await.unwrap();
+        device.set_brightness(1.0).await.unwrap();
+        device.set_brightness(0.0).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 1);
+        for value in [1.01, -0.01] {
+            let result = device.set_brightness(value).await;
+            assert!(matches!(result, Err(CalibrightError::InvalidBrightness(v)) if v == value));
+        }
+        // Adjusting from the top past 1.0
+        let result = device.adjust_brightness(0.1).await;
+        assert!(matches!(result, Err(CalibrightError::InvalidBrightness(_))));
+        assert_eq!(read_number(&fs, &written), 1);
+
+        // NaN can't be clamped, so it is an error with either policy
+        for overflow_policy in [OverflowPolicy::Clamp, OverflowPolicy::Error] {
+            let options = DeviceOptions {
+                overflow_policy,
+                ..device_options(&fs)
+            };
+            let mut device = device_with(options).await.unwrap();
+            let result = device.set_brightness(f64::NAN).await;
+            assert!(matches!(result, Err(CalibrightError::InvalidBrightness(v)) if v.is_nan()));
+            let result = device.adjust_brightness(f64::NAN).await;
+            assert!(matches!(result, Err(CalibrightError::InvalidBrightness(v)) if v.is_nan()));
+        }
+        assert_eq!(read_number(&fs, &written), 1);
+    }
+
+    #[tokio::test]
+    async fn nits_are_linear_in_the_raw_brightness() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        let (fs, mut device) = test_device(50, 100, "max_nits = 400.0").await;
+        assert_close(device.get_brightness_nits().await.unwrap(), 200.0, 1e-9);
+        device.set_brightness_nits(100.0).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 25);
+        device.set_brightness_nits(1000.0).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 100);
+
+        for nits in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let result = device.set_brightness_nits(nits).await;
+            assert!(matches!(result, Err(CalibrightError::InvalidBrightness(_))));
+        }
+        assert_eq!(read_number(&fs, &written), 100);
+
+        let (_, mut device) = test_device(50, 100, "").await;
+        assert!(device.set_brightness_nits(100.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn uncalibrated_brightness_ignores_the_calibration_window() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        let (fs, mut device) = test_device(50, 100, "calibration = [20.0, 80.0]").await;
+        device.set_brightness_uncalibrated(1.0).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 100);
+        device.set_brightness_uncalibrated(1.5).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 100);
+        device.set_brightness_uncalibrated(0.3).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 30);
+
+        // NaN would otherwise end up as a raw value of 0
+        let result = device.set_brightness_uncalibrated(f64::NAN).await;
+        assert!(matches!(result, Err(CalibrightError::InvalidBrightness(v)) if v.is_nan()));
+        assert_eq!(read_number(&fs, &written), 30);
+
+        let options = DeviceOptions {
+            overflow_policy: OverflowPolicy::Error,
+            ..device_options(&fs)
+        };
+        let mut device = device_with(options).await.unwrap();
+        let result = device.set_brightness_uncalibrated(1.5).await;
+        assert!(matches!(result, Err(CalibrightError::InvalidBrightness(v)) if v == 1.5));
+        assert_eq!(read_number(&fs, &written), 30);
+    }
+
+    #[tokio::test]
+    async fn inverted_devices_round_trip() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        let (fs, mut device) = test_device(50, 100, "invert = true").await;
+        for raw in [0, 1, 30, 100] {
+            assert_eq!(device.invert_raw(device.invert_raw(raw)), raw);
+        }
+
+        device.set_brightness(0.3).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 70);
+        // The complemented value reads back as what was set
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "70");
+        assert_close(device.get_brightness().await.unwrap(), 0.3, 1e-9);
+    }
+
+    #[tokio::test]
+    async fn confirming_a_write_caches_what_the_hardware_took() {
+        let (fs, mut device) = test_device(50, 100, "").await;
+        device.set_brightness(0.55).await.unwrap();
+        assert_eq!(device.cached_raw_brightness(), 55);
+
+        // The driver rounds to steps of 10
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "50");
+        device.confirm_raw_brightness().await;
+        assert_eq!(device.cached_raw_brightness(), 50);
+        assert_eq!(device.cached_brightness().unwrap(), 0.5);
+
+        // Without a readable value the written value is assumed
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "");
+        device.set_brightness(0.7).await.unwrap();
+        device.confirm_raw_brightness().await;
+        assert_eq!(device.cached_raw_brightness(), 70);
+    }
+
+    #[tokio::test]
+    async fn effective_brightness_rounds_to_raw_steps() {
+        let written = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        let (fs, mut device) = test_device(5, 10, "").await;
+        assert_close(device.effective_brightness(0.44).unwrap(), 0.4, 1e-9);
+        // The lowest raw value is 1, which doesn't turn off the backlight
+        assert_close(device.effective_brightness(0.03).unwrap(), 0.1, 1e-9);
+
+        // Nothing was written, and setting lands where predicted
+        assert_eq!(read_number(&fs, &written), 5);
+        device.set_brightness(0.03).await.unwrap();
+        assert_eq!(read_number(&fs, &written), 1);
+    }
+
+    #[tokio::test]
+    async fn min_step_percent_follows_the_raw_range() {
+        let (_, device) = test_device(5, 10, "").await;
+        assert_close(device.min_step_percent().unwrap(), 0.1, 1e-9);
+        let (_, device) = test_device(10, 10, "").await;
+        assert_close(device.min_step_percent().unwrap(), 0.1, 1e-9);
+
+        let (_, device) = test_device(32768, 65536, "").await;
+        assert_close(device.min_step_percent().unwrap(), 1.0 / 65536.0, 1e-12);
+        // With root_scaling, steps are larger at the low end
+        let (_, low) = test_device(1, 65536, "root_scaling = 2.0").await;
+        let (_, high) = test_device(65535, 65536, "root_scaling = 2.0").await;
+        assert!(low.min_step_percent().unwrap() > high.min_step_percent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn empty_actual_brightness_falls_back_to_brightness() {
+        let read_file = backlight_file(NAME, FILE_BRIGHTNESS);
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 50, 100);
+        let toml = "ddcci_max_tries_write_read = 5\nddcci_read_base_ms = 1";
+        let device = device_in(&fs, toml).await.unwrap();
+
+        fs.fs.insert(&read_file, "\n");
+        fs.fs
+            .insert(backlight_file(NAME, FILE_BRIGHTNESS_WRITE), "40\n");
+        let reads = fs.reads(&read_file);
+        assert_eq!(device.peek_raw().await.unwrap(), 40);
+        // Read once, and once more to tell that it is empty, without retries
+        assert_eq!(fs.reads(&read_file) - reads, 2);
+        assert_eq!(device.last_read_retries(), 0);
+
+        // Failing reads are still retried before falling back
+        fs.fs.insert(&read_file, "50\n");
+        fs.fail_reads(&read_file, 2);
+        assert_eq!(device.peek_raw().await.unwrap(), 50);
+        assert_eq!(device.last_read_retries(), 2);
+    }
+
+    #[tokio::test]
+    async fn failing_amdgpu_brightness_falls_back_to_actual_brightness() {
+        const AMDGPU: &str = "amdgpu_bl0";
+        let read_file = backlight_file(AMDGPU, FILE_BRIGHTNESS_AMD);
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, AMDGPU, 50, 100);
+        let config = config(&format!(
+            "[{AMDGPU}]\nddcci_max_tries_write_read = 2\nddcci_read_base_ms = 1"
+        ));
+        let device = Device::new(
+            Subsystem::Backlight,
+            &AMDGPU.to_string(),
+            &config,
+            device_options(&fs),
+        )
+        .await
+        .unwrap();
+        assert_eq!(device.read_brightness_file, read_file);
+
+        fs.fs
+            .insert(backlight_file(AMDGPU, FILE_BRIGHTNESS), "40\n");
+        fs.fail_reads(&read_file, 2);
+        assert_eq!(device.peek_raw().await.unwrap(), 40);
+
+        fs.fs.insert(&read_file, "\n");
+        assert_eq!(device.peek_raw().await.unwrap(), 40);
+
+        fs.fs.insert(&read_file, "60\n");
+        assert_eq!(device.peek_raw().await.unwrap(), 60);
+    }
+
+    #[tokio::test]
+    async fn connector_is_found_through_the_device_link() {
+        let connector_path = "/sys/devices/pci0000:00/0000:00:02.0/drm/card0/card0-DP-1";
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        insert_backlight(&fs, "other", 50, 100);
+        fs.insert(Path::new(connector_path).join("enabled"), "enabled\n");
+        fs.symlink(backlight_file(NAME, "device"), connector_path);
+
+        let device_path = Path::new(DEVICES_PATH).join(NAME);
+        assert_eq!(
+            connector_name(&*fs, &device_path).await.as_deref(),
+            Some("DP-1")
+        );
+        let other_path = Path::new(DEVICES_PATH).join("other");
+        assert_eq!(connector_name(&*fs, &other_path).await, None);
+
+        // The config of the connector applies to the device
+        let config = config("[DP-1]\ncalibration = [20.0, 80.0]");
+        let device = Device::new(
+            Subsystem::Backlight,
+            &NAME.into(),
+            &config,
+            device_options(&fs),
+        )
+        .await
+        .unwrap();
+        assert_eq!(device.connector.as_deref(), Some("DP-1"));
+        assert_eq!(device.config.calibration, [0.2, 0.8]);
+    }
+
+    #[tokio::test]
+    async fn brightness_min_only_raises_an_unset_calibration() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS_MIN), "10\n");
+
+        let device = device_in(&fs, "").await.unwrap();
+        assert_close(device.config.calibration[0], 0.1, 1e-9);
+        // Explicitly set to what is also the default
+        let device = device_in(&fs, "calibration = [0.0, 100.0]").await.unwrap();
+        assert_eq!(device.config.calibration, [0.0, 1.0]);
+        let device = device_in(&fs, "calibration = [5.0, 100.0]").await.unwrap();
+        assert_eq!(device.config.calibration, [0.05, 1.0]);
+    }
+
+    #[tokio::test]
+    async fn max_brightness_reads_are_retried() {
+        let max_file = backlight_file(NAME, FILE_MAX_BRIGHTNESS);
+        let toml = "ddcci_max_tries_write_read = 3\nddcci_read_base_ms = 1";
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 50, 100);
+        fs.fail_reads(&max_file, 1);
+        let device = device_in(&fs, toml).await.unwrap();
+        assert_eq!(device.max_brightness(), 100);
+        assert_eq!(fs.reads(&max_file), 2);
+
+        // Drivers that report 0 while setting up are retried as well
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 50, 0);
+        assert!(device_in(&fs, toml).await.is_err());
+        assert_eq!(fs.reads(&max_file), 3);
     }
 }
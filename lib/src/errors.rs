@@ -23,9 +23,61 @@ pub enum CalibrightError {
     #[error("{0}")]
     ParseInt(#[from] std::num::ParseIntError),
 
+    #[error("Failed to set brightness via D-Bus ({dbus}) and the fallback ({fallback})")]
+    SetBrightness {
+        #[source]
+        dbus: zbus::Error,
+        fallback: Box<CalibrightError>,
+    },
+
+    #[error("logind is required but unavailable: {0}")]
+    LogindRequired(#[source] Box<CalibrightError>),
+
+    #[error(
+        "Invalid config{}: {message}",
+        path.as_ref().map(|path| format!(" file {}", path.display())).unwrap_or_default()
+    )]
+    Config {
+        /// The config file, if the config was read from one.
+        path: Option<std::path::PathBuf>,
+        message: String,
+    },
+
+    #[error("{}: {source}", name.to_string_lossy())]
+    Device {
+        name: std::ffi::OsString,
+        #[source]
+        source: Box<CalibrightError>,
+    },
+
+    #[error(
+        "Permission denied writing {}, run calibright in a logind session or grant write access with a udev rule",
+        path.display()
+    )]
+    PermissionDenied {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Brightness can't be set in read-only mode")]
+    ReadOnly,
+
     #[error("No matching devices exist")]
     NoDevices,
 
+    #[error("Invalid brightness {0}, expected a number in the range of 0.0 to 1.0")]
+    InvalidBrightness(f64),
+
+    #[error("Timed out after {0:?} waiting for the devices to reach the brightness")]
+    Timeout(std::time::Duration),
+
+    #[error("{} timed out after {waited:?}", device.to_string_lossy())]
+    TimedOut {
+        device: std::ffi::OsString,
+        waited: std::time::Duration,
+    },
+
     #[error("Invalid scale parameters: {low} > {high}")]
     InvalidScaleParameters { low: f64, high: f64 },
 
@@ -35,3 +87,21 @@ pub enum CalibrightError {
     #[error("Unknown error")]
     Unknown,
 }
+
+impl CalibrightError {
+    /// Render this error together with all of its causes, one per line, e.g. for bug reports.
+    pub fn full_chain(&self) -> String {
+        let mut chain = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            let message = cause.to_string();
+            // Most variants already include the message of their direct cause
+            if !chain.contains(&message) {
+                chain.push_str("\nCaused by: ");
+                chain.push_str(&message);
+            }
+            source = cause.source();
+        }
+        chain
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::future::Future;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A boxed future returned by [`FsProvider`] methods.
+pub type FsFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// The file system operations used to discover, read and write devices in sysfs.
+///
+/// [`TokioFs`] is used by default. Another implementation, such as [`MemoryFs`], can be passed
+/// to [`crate::CalibrightBuilder::with_fs_provider`] to run without real hardware, e.g. in tests.
+pub trait FsProvider: Send + Sync {
+    /// Read the contents of a file.
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>>;
+
+    /// Replace the contents of an existing file.
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()>;
+
+    /// The names of the entries of a directory.
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>>;
+
+    /// Whether a file exists (without reading it).
+    fn exists<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool>;
+
+    /// Whether an existing file can be opened for writing.
+    fn can_write<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool>;
+
+    /// The absolute path with all symlinks resolved, e.g. to find the DRM connector a device
+    /// belongs to.
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> FsFuture<'a, PathBuf>;
+
+    /// Whether changes of the files can be watched with inotify, i.e. whether they are real
+    /// files. Defaults to `true`.
+    fn can_watch(&self) -> bool {
+        true
+    }
+}
+
+/// Share a file system, e.g. to check what was written to a [`MemoryFs`] after passing it to
+/// [`crate::CalibrightBuilder::with_fs_provider`].
+impl<T: FsProvider + ?Sized> FsProvider for Arc<T> {
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>> {
+        (**self).read(path)
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()> {
+        (**self).write(path, contents)
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>> {
+        (**self).read_dir(path)
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        (**self).exists(path)
+    }
+
+    fn can_write<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        (**self).can_write(path)
+    }
+
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> FsFuture<'a, PathBuf> {
+        (**self).canonicalize(path)
+    }
+
+    fn can_watch(&self) -> bool {
+        (**self).can_watch()
+    }
+}
+
+/// The real file system, accessed with [`tokio::fs`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioFs;
+
+impl FsProvider for TokioFs {
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            let mut file = tokio::fs::File::open(path).await?;
+            // sysfs attributes are small, avoid the metadata lookup of `tokio::fs::read`
+            let mut contents = Vec::with_capacity(32);
+            file.read_to_end(&mut contents).await?;
+            Ok(contents)
+        })
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(path)
+                .await?;
+            file.write_all(contents).await
+        })
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>> {
+        Box::pin(async move {
+            let mut entries = tokio::fs::read_dir(path).await?;
+            let mut names = Vec::new();
+            while let Some(entry) = entries.next_entry().await? {
+                names.push(entry.file_name());
+            }
+            Ok(names)
+        })
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        Box::pin(tokio::fs::try_exists(path))
+    }
+
+    fn can_write<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        Box::pin(async move { Ok(OpenOptions::new().write(true).open(path).await.is_ok()) })
+    }
+
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> FsFuture<'a, PathBuf> {
+        Box::pin(tokio::fs::canonicalize(path))
+    }
+}
+
+/// An in-memory file system, e.g. to test reading and writing without real devices.
+///
+/// Like sysfs, only files that were added with [`MemoryFs::insert`] can be written, and
+/// directories exist implicitly as long as they contain a file. Symlinks can be added with
+/// [`MemoryFs::symlink`]. The files can't be watched, so [`crate::Calibright::next`] doesn't see
+/// changes of them.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+    links: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+impl MemoryFs {
+    /// Create an empty file system.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file or replace its contents.
+    pub fn insert(&self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.into(), contents.into());
+    }
+
+    /// Remove a file, returning its contents.
+    pub fn remove(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().remove(path.as_ref())
+    }
+
+    /// The contents of a file, e.g. to check what was written.
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(path.as_ref()).cloned()
+    }
+
+    /// Add a symlink at `link` to the absolute path `target`, like the `device` link of a sysfs
+    /// device. Only [`FsProvider::canonicalize`] follows it.
+    pub fn symlink(&self, link: impl Into<PathBuf>, target: impl Into<PathBuf>) {
+        self.links
+            .lock()
+            .unwrap()
+            .insert(link.into(), target.into());
+    }
+
+    /// Whether there is a file or a directory containing one at `path`.
+    fn contains(&self, path: &Path) -> bool {
+        self.files
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|file| file.starts_with(path))
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found", path.display()),
+    )
+}
+
+impl FsProvider for MemoryFs {
+    fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>> {
+        Box::pin(async move { self.get(path).ok_or_else(|| not_found(path)) })
+    }
+
+    fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            match self.files.lock().unwrap().get_mut(path) {
+                Some(file) => {
+                    *file = contents.to_vec();
+                    Ok(())
+                }
+                None => Err(not_found(path)),
+            }
+        })
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>> {
+        Box::pin(async move {
+            let names: BTreeSet<OsString> = self
+                .files
+                .lock()
+                .unwrap()
+                .keys()
+                .filter_map(
+                    |file| match file.strip_prefix(path).ok()?.components().next() {
+                        Some(Component::Normal(name)) => Some(name.to_os_string()),
+                        _ => None,
+                    },
+                )
+                .collect();
+            if names.is_empty() {
+                return Err(not_found(path));
+            }
+            Ok(names.into_iter().collect())
+        })
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        Box::pin(async move { Ok(self.files.lock().unwrap().contains_key(path)) })
+    }
+
+    fn can_write<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+        self.exists(path)
+    }
+
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> FsFuture<'a, PathBuf> {
+        Box::pin(async move {
+            let links = self.links.lock().unwrap().clone();
+            let mut resolved = PathBuf::new();
+            for component in path.components() {
+                resolved.push(component);
+                // Links can point to other links, but not endlessly
+                for _ in 0..40 {
+                    match links.get(&resolved) {
+                        Some(target) => resolved = target.clone(),
+                        None => break,
+                    }
+                }
+            }
+            if self.contains(&resolved) {
+                Ok(resolved)
+            } else {
+                Err(not_found(path))
+            }
+        })
+    }
+
+    fn can_watch(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use std::ffi::OsStr;
+    use std::time::Duration;
+
+    use crate::consts::*;
+    use crate::device::{DeviceOptions, LogindNames};
+    use crate::{Calibright, CalibrightBuilder, CalibrightConfig, DeviceConfig};
+
+    /// Add a backlight with the given raw and max brightness to `fs`.
+    pub(crate) fn insert_backlight(fs: &MemoryFs, name: &str, raw: u32, max: u32) {
+        let device_path = Path::new(DEVICES_PATH).join(name);
+        fs.insert(device_path.join(FILE_MAX_BRIGHTNESS), format!("{max}\n"));
+        fs.insert(device_path.join(FILE_BRIGHTNESS), format!("{raw}\n"));
+        fs.insert(device_path.join(FILE_BRIGHTNESS_WRITE), format!("{raw}\n"));
+    }
+
+    /// The path of a file of the backlight with the given name.
+    pub(crate) fn backlight_file(name: &str, file: &str) -> PathBuf {
+        Path::new(DEVICES_PATH).join(name).join(file)
+    }
+
+    /// The number in a file of `fs`, e.g. the raw brightness written to a backlight.
+    pub(crate) fn read_number(fs: &MemoryFs, path: impl AsRef<Path>) -> u32 {
+        let contents = fs.get(path).expect("file exists");
+        String::from_utf8(contents).unwrap().trim().parse().unwrap()
+    }
+
+    /// Read a config like the config file is read.
+    pub(crate) fn config(toml: &str) -> CalibrightConfig {
+        CalibrightConfig::from_deserializer(toml::Deserializer::new(toml), &DeviceConfig::default())
+            .unwrap()
+    }
+
+    /// A [`MemoryFs`] whose reads and writes are counted and can be made to fail, e.g. to test
+    /// retries.
+    #[derive(Default)]
+    pub(crate) struct FlakyFs {
+        pub fs: MemoryFs,
+        reads: Mutex<HashMap<PathBuf, u32>>,
+        writes: Mutex<HashMap<PathBuf, u32>>,
+        failures: Mutex<HashMap<PathBuf, u32>>,
+        delays: Mutex<HashMap<PathBuf, Duration>>,
+        denied_writes: Mutex<Vec<PathBuf>>,
+    }
+
+    impl FlakyFs {
+        /// Fail the next `count` reads of `path`.
+        pub fn fail_reads(&self, path: impl Into<PathBuf>, count: u32) {
+            self.failures.lock().unwrap().insert(path.into(), count);
+        }
+
+        /// Delay every read of `path` by `delay`, like a slow (e.g. DDC) device.
+        pub fn delay_reads(&self, path: impl Into<PathBuf>, delay: Duration) {
+            self.delays.lock().unwrap().insert(path.into(), delay);
+        }
+
+        /// Deny writing `path` from now on, like a file that lost its write permission.
+        pub fn deny_writes(&self, path: impl Into<PathBuf>) {
+            self.denied_writes.lock().unwrap().push(path.into());
+        }
+
+        /// The number of reads of `path` so far.
+        pub fn reads(&self, path: impl AsRef<Path>) -> u32 {
+            let reads = self.reads.lock().unwrap();
+            reads.get(path.as_ref()).copied().unwrap_or(0)
+        }
+
+        /// The number of writes of `path` so far.
+        pub fn writes(&self, path: impl AsRef<Path>) -> u32 {
+            let writes = self.writes.lock().unwrap();
+            writes.get(path.as_ref()).copied().unwrap_or(0)
+        }
+    }
+
+    impl FsProvider for FlakyFs {
+        fn read<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<u8>> {
+            Box::pin(async move {
+                *self.reads.lock().unwrap().entry(path.into()).or_default() += 1;
+                let delay = self.delays.lock().unwrap().get(path).copied();
+                if let Some(delay) = delay {
+                    tokio::time::sleep(delay).await;
+                }
+                if let Some(failures) = self.failures.lock().unwrap().get_mut(path) {
+                    if *failures > 0 {
+                        *failures -= 1;
+                        return Err(io::Error::other("flaky read"));
+                    }
+                }
+                self.fs.read(path).await
+            })
+        }
+
+        fn write<'a>(&'a self, path: &'a Path, contents: &'a [u8]) -> FsFuture<'a, ()> {
+            Box::pin(async move {
+                *self.writes.lock().unwrap().entry(path.into()).or_default() += 1;
+                if self.denied_writes.lock().unwrap().iter().any(|p| p == path) {
+                    return Err(io::ErrorKind::PermissionDenied.into());
+                }
+                self.fs.write(path, contents).await
+            })
+        }
+
+        fn read_dir<'a>(&'a self, path: &'a Path) -> FsFuture<'a, Vec<OsString>> {
+            self.fs.read_dir(path)
+        }
+
+        fn exists<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+            self.fs.exists(path)
+        }
+
+        fn can_write<'a>(&'a self, path: &'a Path) -> FsFuture<'a, bool> {
+            self.fs.can_write(path)
+        }
+
+        fn canonicalize<'a>(&'a self, path: &'a Path) -> FsFuture<'a, PathBuf> {
+            self.fs.canonicalize(path)
+        }
+
+        fn can_watch(&self) -> bool {
+            self.fs.can_watch()
+        }
+    }
+
+    /// logind names nobody owns, so that tests don't change anything through the logind of the
+    /// system running them and writes always fall back to the file system.
+    const UNOWNED_LOGIND: [&str; 3] = [
+        "org.calibright.Test",
+        "org.freedesktop.login1.Session",
+        "/org/freedesktop/login1/session/auto",
+    ];
+
+    /// The options of devices in `fs`, see [`UNOWNED_LOGIND`].
+    pub(crate) fn device_options<T: FsProvider + 'static>(fs: &Arc<T>) -> DeviceOptions {
+        let [service, session_interface, session_path] = UNOWNED_LOGIND;
+        DeviceOptions {
+            fs: fs.clone(),
+            logind_names: LogindNames::new(service, session_interface, session_path).unwrap(),
+            ..DeviceOptions::default()
+        }
+    }
+
+    /// A builder for the devices in `fs` with a default config, see [`UNOWNED_LOGIND`].
+    pub(crate) fn builder<T: FsProvider + 'static>(fs: &Arc<T>) -> CalibrightBuilder<'static> {
+        let [service, session_interface, session_path] = UNOWNED_LOGIND;
+        CalibrightBuilder::new()
+            .with_config(config(""))
+            .with_fs_provider(fs.clone())
+            .with_logind_names(service, session_interface, session_path)
+    }
+
+    /// A [`Calibright`] for the devices in `fs`, see [`builder`].
+    pub(crate) async fn calibright<T: FsProvider + 'static>(fs: &Arc<T>) -> Calibright {
+        builder(fs).build().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn memory_fs_lists_directories_of_files() {
+        let fs = MemoryFs::new();
+        insert_backlight(&fs, "b", 1, 10);
+        insert_backlight(&fs, "a", 1, 10);
+
+        let names = fs.read_dir(Path::new(DEVICES_PATH)).await.unwrap();
+        assert_eq!(names, [OsString::from("a"), OsString::from("b")]);
+        let err = fs.read_dir(Path::new(LEDS_PATH)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn memory_fs_only_writes_existing_files() {
+        let fs = MemoryFs::new();
+        let path = backlight_file("a", FILE_BRIGHTNESS_WRITE);
+        assert!(fs.write(&path, b"1").await.is_err());
+        assert!(!fs.can_write(&path).await.unwrap());
+
+        fs.insert(&path, "0");
+        fs.write(&path, b"1").await.unwrap();
+        assert_eq!(fs.get(&path).unwrap(), b"1");
+        assert!(fs.can_write(&path).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reads_brightness_from_memory_fs() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, "calibright_test", 50, 100);
+        let mut calibright = calibright(&fs).await;
+
+        assert_eq!(calibright.matched_device_names(), ["calibright_test"]);
+        assert_eq!(calibright.get_brightness().await.unwrap(), 0.5);
+
+        fs.insert(backlight_file("calibright_test", FILE_BRIGHTNESS), "100");
+        assert_eq!(calibright.get_brightness().await.unwrap(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn writes_brightness_to_memory_fs() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, "calibright_test", 50, 100);
+        let mut calibright = calibright(&fs).await;
+
+        calibright.set_brightness(0.25).await.unwrap();
+        let written = backlight_file("calibright_test", FILE_BRIGHTNESS_WRITE);
+        assert_eq!(read_number(&fs, written), 25);
+        assert_eq!(
+            calibright.cached_raw_brightness(),
+            [(OsStr::new("calibright_test"), 25)]
+        );
+        assert_eq!(
+            calibright.last_write_method("calibright_test".as_ref()),
+            Some(crate::WriteMethod::Sysfs)
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! A small JSON reader for the config, so that JSON configs go through the same serde
+//! deserializers (and validation) as the TOML config file without another dependency.
+//!
+//! JSON is read into a [`toml::Value`]. As TOML has no null, `null` members of objects are
+//! treated as missing.
+
+use serde::de::DeserializeOwned;
+use toml::{Table, Value};
+
+/// Deserialize JSON, returning an error message with the line of the error on failure.
+pub fn deserialize_json_str<T>(contents: &str) -> std::result::Result<T, String>
+where
+    T: DeserializeOwned,
+{
+    let mut parser = Parser {
+        contents: contents.as_bytes(),
+        pos: 0,
+    };
+    let value = parser
+        .document()
+        .map_err(|message| format!("line {}: {message}", parser.line()))?;
+    T::deserialize(value).map_err(|err| err.message().to_string())
+}
+
+struct Parser<'a> {
+    contents: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// The line of the current position, starting at 1.
+    fn line(&self) -> usize {
+        1 + self.contents[..self.pos.min(self.contents.len())]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count()
+    }
+
+    fn document(&mut self) -> Result<Value, String> {
+        let value = self.value()?.ok_or("unexpected null")?;
+        self.skip_whitespace();
+        if self.pos < self.contents.len() {
+            return Err("unexpected content after the end of the document".into());
+        }
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.contents.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.contents.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        match self.peek() {
+            Some(b) if b == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(b) => Err(format!(
+                "expected `{}`, found `{}`",
+                expected as char, b as char
+            )),
+            None => Err(format!("expected `{}`, found the end", expected as char)),
+        }
+    }
+
+    /// A value, `None` for `null`.
+    fn value(&mut self) -> Result<Option<Value>, String> {
+        match self.peek() {
+            Some(b'{') => self.object().map(Some),
+            Some(b'[') => self.array().map(Some),
+            Some(b'"') => self.string().map(|s| Some(Value::String(s))),
+            Some(b'-' | b'0'..=b'9') => self.number().map(Some),
+            Some(_) => self.literal(),
+            None => Err("unexpected end".into()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect(b'{')?;
+        let mut table = Table::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Table(table));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err("expected a string as the key".into());
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            if let Some(value) = self.value()? {
+                if table.insert(key.clone(), value).is_some() {
+                    return Err(format!("duplicate key `{key}`"));
+                }
+            }
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                _ => break,
+            }
+        }
+        self.expect(b'}')?;
+        Ok(Value::Table(table))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect(b'[')?;
+        let mut array = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(array));
+        }
+        loop {
+            array.push(self.value()?.ok_or("null is not allowed in arrays")?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                _ => break,
+            }
+        }
+        self.expect(b']')?;
+        Ok(Value::Array(array))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut string = Vec::new();
+        loop {
+            let Some(&b) = self.contents.get(self.pos) else {
+                return Err("unterminated string".into());
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escaped) = self.contents.get(self.pos) else {
+                        return Err("unterminated string".into());
+                    };
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(format!("invalid escape `\\{}`", escaped as char)),
+                    };
+                    string.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                0x00..=0x1f => return Err("control character in string".into()),
+                _ => string.push(b),
+            }
+        }
+        String::from_utf8(string).map_err(|_| "invalid UTF-8 in string".into())
+    }
+
+    /// The character of a `\u` escape, after the `\u`, including surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if self.contents.get(self.pos..self.pos + 2) != Some(b"\\u") {
+                return Err("unpaired surrogate in `\\u` escape".into());
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err("unpaired surrogate in `\\u` escape".into());
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| "invalid `\\u` escape".into())
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .contents
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or("invalid `\\u` escape")?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let mut is_float = false;
+        while let Some(&b) = self.contents.get(self.pos) {
+            match b {
+                b'0'..=b'9' | b'-' | b'+' => {}
+                b'.' | b'e' | b'E' => is_float = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        // Only ASCII was consumed
+        let number = std::str::from_utf8(&self.contents[start..self.pos]).unwrap();
+        if !is_json_number(number) {
+            return Err(format!("invalid number `{number}`"));
+        }
+        if !is_float {
+            if let Ok(integer) = number.parse() {
+                return Ok(Value::Integer(integer));
+            }
+        }
+        number
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| format!("invalid number `{number}`"))
+    }
+
+    /// `true`, `false` or `null` (as `None`).
+    fn literal(&mut self) -> Result<Option<Value>, String> {
+        for (literal, value) in [
+            (&b"true"[..], Some(Value::Boolean(true))),
+            (b"false", Some(Value::Boolean(false))),
+            (b"null", None),
+        ] {
+            if self.contents[self.pos..].starts_with(literal) {
+                self.pos += literal.len();
+                return Ok(value);
+            }
+        }
+        Err("expected a value".into())
+    }
+}
+
+/// Whether `number` follows the JSON grammar, which is stricter than Rust's (e.g. `01`, `+1` and
+/// `1.` are invalid).
+fn is_json_number(number: &str) -> bool {
+    fn digits(s: &str) -> (&str, &str) {
+        s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+    }
+
+    let rest = number.strip_prefix('-').unwrap_or(number);
+    let (int, rest) = digits(rest);
+    if int.is_empty() || int.len() > 1 && int.starts_with('0') {
+        return false;
+    }
+    let rest = match rest.strip_prefix('.') {
+        Some(rest) => match digits(rest) {
+            ("", _) => return false,
+            (_, rest) => rest,
+        },
+        None => rest,
+    };
+    match rest.strip_prefix(['e', 'E']) {
+        Some(rest) => {
+            let rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+            matches!(digits(rest), (exp, "") if !exp.is_empty())
+        }
+        None => rest.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> std::result::Result<Value, String> {
+        deserialize_json_str(json)
+    }
+
+    #[test]
+    fn reads_nested_values() {
+        let value =
+            parse(r#"{"global": {"calibration": [5, 95.5], "invert": false, "alias": "a\"é😀"}}"#)
+                .unwrap();
+        let global = &value["global"];
+        assert_eq!(
+            global["calibration"],
+            Value::Array(vec![Value::Integer(5), Value::Float(95.5)])
+        );
+        assert_eq!(global["invert"], Value::Boolean(false));
+        assert_eq!(global["alias"], Value::String("a\"é😀".into()));
+    }
+
+    #[test]
+    fn null_members_are_missing() {
+        let value = parse(r#"{"global": {"gamma": null}}"#).unwrap();
+        assert_eq!(value["global"], Value::Table(Table::new()));
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        assert_eq!(
+            parse("{\n  \"global\": {\n    \"gamma\": 01\n  }\n}").unwrap_err(),
+            "line 3: invalid number `01`"
+        );
+        assert!(parse(r#"{"a": 1} 2"#).is_err());
+        assert!(parse(r#"{"a": 1, "a": 2}"#).is_err());
+        assert!(parse(r#"{"a": [1, null]}"#).is_err());
+        assert!(parse(r#"{"a": "\x"}"#).is_err());
+        assert!(parse(r#"{"a": 1.}"#).is_err());
+        assert!(parse(r#"{"a": -1.5e+2}"#).is_ok());
+    }
+}
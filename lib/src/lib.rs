@@ -5,46 +5,116 @@
 
 #[macro_use]
 mod util;
+mod brightness;
+mod coalesce;
 mod config;
 mod consts;
+#[cfg(feature = "dbus-watch")]
+mod dbus_watcher;
+#[cfg(feature = "i2c")]
+mod ddc;
 mod device;
 mod errors;
+mod fs;
+#[cfg(feature = "json")]
+mod json;
+mod refresh;
+mod shared;
+mod state;
+#[cfg(feature = "udev")]
+mod uevent;
 #[cfg(feature = "watch")]
 mod watcher;
 
-use std::collections::HashMap;
-use std::ffi::OsString;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use futures_util::future::join_all;
 use regex::Regex;
-use tokio::fs::read_dir;
+use tokio::time::Instant;
 
+pub use crate::brightness::{
+    Aggregation, Brightness, OverflowPolicy, ReadErrorPolicy, SetAggregation,
+};
+use crate::coalesce::CoalescedWriter;
 pub use crate::config::{CalibrightConfig, DeviceConfig};
+pub use crate::consts::BRIGHTNESS_RANGE;
 use crate::consts::*;
-use crate::device::Device;
+use crate::device::{connector_name, find_max_file, Device, DeviceOptions};
+pub use crate::device::{
+    BacklightScale, Capabilities, DeviceSnapshot, LogindNames, Subsystem, WriteMethod,
+};
 pub use crate::errors::CalibrightError;
 use crate::errors::*;
+pub use crate::fs::{FsFuture, FsProvider, MemoryFs, TokioFs};
+use crate::refresh::BackgroundRefresh;
+pub use crate::shared::SharedCalibright;
+use crate::state::State;
 use crate::util::*;
 #[cfg(feature = "watch")]
 use crate::watcher::*;
 
 make_log_macro!(debug, "calibright");
+make_log_macro!(warn, "calibright");
 
 /// Used to construct [`Calibright`]
 pub struct CalibrightBuilder<'a> {
-    device_regex: &'a str,
+    device_regex: Option<&'a str>,
+    device_names: Option<&'a [&'a str]>,
+    logind_names: Option<[&'a str; 3]>,
+    subsystems: Vec<Subsystem>,
     config: Option<CalibrightConfig>,
+    defaults: DeviceConfig,
+    device_options: DeviceOptions,
+    relative_mode: bool,
+    get_aggregation: Aggregation,
+    set_aggregation: SetAggregation,
+    device_groups: Vec<Vec<OsString>>,
+    display: DeviceConfig,
+    smoothing_factor: f64,
+    background_refresh: Option<Duration>,
+    coalesced_writes: bool,
+    state_file: Option<PathBuf>,
     #[cfg(feature = "watch")]
     poll_interval: Duration,
+    #[cfg(feature = "watch")]
+    hotplug: bool,
+    #[cfg(feature = "watch")]
+    mirror_from: Option<OsString>,
+    #[cfg(feature = "watch")]
+    on_change: Option<ChangeCallback>,
 }
 
 impl<'a> Default for CalibrightBuilder<'a> {
     fn default() -> Self {
         Self {
-            device_regex: ".",
+            device_regex: None,
+            device_names: None,
+            logind_names: None,
+            subsystems: vec![Subsystem::Backlight],
             config: None,
+            defaults: DeviceConfig::default(),
+            device_options: DeviceOptions::default(),
+            relative_mode: false,
+            get_aggregation: Aggregation::default(),
+            set_aggregation: SetAggregation::default(),
+            device_groups: Vec::new(),
+            display: DeviceConfig::default(),
+            smoothing_factor: 1.0,
+            background_refresh: None,
+            coalesced_writes: false,
+            state_file: None,
             #[cfg(feature = "watch")]
             poll_interval: Duration::from_secs(2),
+            #[cfg(feature = "watch")]
+            hotplug: true,
+            #[cfg(feature = "watch")]
+            mirror_from: None,
+            #[cfg(feature = "watch")]
+            on_change: None,
         }
     }
 }
@@ -55,9 +125,30 @@ impl<'a> CalibrightBuilder<'a> {
         CalibrightBuilder::default()
     }
 
-    /// Defaults to `"."` (matches all devices).
+    /// Create a [`CalibrightBuilder`] for exactly the devices with the given names, e.g. when
+    /// they are already known from udev. The device directories aren't scanned, and building
+    /// fails if any of the devices doesn't exist in the [`CalibrightBuilder::with_subsystems`].
+    ///
+    /// The device regex is ignored. With the `watch` feature, only these devices are picked up
+    /// again when they are hotplugged.
+    pub fn from_devices(device_names: &'a [&'a str]) -> Self {
+        Self {
+            device_names: Some(device_names),
+            ..CalibrightBuilder::default()
+        }
+    }
+
+    /// Defaults to `$CALIBRIGHT_DEVICE` if set, otherwise `"."` (matches all devices).
     pub fn with_device_regex(mut self, device_regex: &'a str) -> Self {
-        self.device_regex = device_regex;
+        self.device_regex = Some(device_regex);
+        self
+    }
+
+    /// The sysfs classes to scan for devices.
+    ///
+    /// Defaults to `[Subsystem::Backlight]`.
+    pub fn with_subsystems(mut self, subsystems: &[Subsystem]) -> Self {
+        self.subsystems = subsystems.to_vec();
         self
     }
 
@@ -67,6 +158,277 @@ impl<'a> CalibrightBuilder<'a> {
         self
     }
 
+    /// Set the default global `calibration` used when no config is passed to
+    /// [`CalibrightBuilder::with_config`]. Values are given as 0-100, like in the config file.
+    /// [`CalibrightBuilder::build`] fails if it is out of range, even if a config was passed.
+    ///
+    /// Defaults to `[0.0, 100.0]`.
+    pub fn with_default_calibration(mut self, calibration: [f64; 2]) -> Self {
+        self.defaults.calibration = calibration.map(|val| val / 100.0);
+        self.defaults.calibration_set = true;
+        self
+    }
+
+    /// Set the default global `root_scaling` used when no config is passed to
+    /// [`CalibrightBuilder::with_config`]. [`CalibrightBuilder::build`] fails if it is out of
+    /// range, even if a config was passed.
+    ///
+    /// Defaults to `1.0`.
+    pub fn with_default_root_scaling(mut self, root_scaling: f64) -> Self {
+        self.defaults.root_scaling = root_scaling;
+        self
+    }
+
+    /// Remap the brightness reported by [`Calibright::get_brightness`] into the given window,
+    /// e.g. for how a status bar displays it. Values are given as 0-100, like `calibration` in the
+    /// config file. What is written by [`Calibright::set_brightness`] isn't affected.
+    ///
+    /// Defaults to `[0.0, 100.0]`.
+    pub fn with_display_calibration(mut self, calibration: [f64; 2]) -> Self {
+        self.display.calibration = calibration.map(|val| val / 100.0);
+        self
+    }
+
+    /// Curve the brightness reported by [`Calibright::get_brightness`] like `root_scaling` in the
+    /// config file, before [`CalibrightBuilder::with_display_calibration`] is applied. What is
+    /// written by [`Calibright::set_brightness`] isn't affected.
+    ///
+    /// Defaults to `1.0`.
+    pub fn with_display_root_scaling(mut self, root_scaling: f64) -> Self {
+        self.display.root_scaling = root_scaling;
+        self
+    }
+
+    /// Smooth the brightness reported by [`Calibright::get_brightness`] with an exponential moving
+    /// average, e.g. so that a status bar polling a DDC/CI monitor doesn't look jittery. Each read
+    /// is blended in with the weight `smoothing_factor` (greater than 0.0, up to 1.0). What is
+    /// written by [`Calibright::set_brightness`] isn't affected.
+    ///
+    /// Defaults to `1.0`, which reports every read as is.
+    pub fn with_smoothing_factor(mut self, smoothing_factor: f64) -> Self {
+        self.smoothing_factor = smoothing_factor;
+        self
+    }
+
+    #[cfg(feature = "helper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "helper")))]
+    /// Command used to write the brightness when both the logind D-Bus call and the sysfs write
+    /// fail, e.g. `["pkexec", "/usr/local/bin/calibright-helper"]`.
+    /// The subsystem, device name and raw brightness are appended as arguments.
+    ///
+    /// # Security
+    ///
+    /// The command is run with elevated privileges, so it must not be writable by unprivileged
+    /// users, and it should validate its arguments (e.g. only accept known subsystems, device names
+    /// without path separators and numeric values) before writing to sysfs.
+    ///
+    /// Defaults to no helper.
+    pub fn with_privileged_helper<I, S>(mut self, command: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.device_options.privileged_helper = Some(command.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// When enabled, setting the brightness performs all of the calculations but doesn't write
+    /// anything to the devices. The values that would have been written can be queried with
+    /// [`Calibright::cached_raw_brightness`].
+    ///
+    /// Defaults to `false`.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.device_options.dry_run = dry_run;
+        self
+    }
+
+    /// Number of perceptually even steps used by [`Calibright::step_up`] and
+    /// [`Calibright::step_down`].
+    ///
+    /// Defaults to `20`.
+    pub fn with_step_count(mut self, step_count: u32) -> Self {
+        self.device_options.step_count = step_count;
+        self
+    }
+
+    /// When enabled, failing to reach logind over D-Bus is an error, instead of silently
+    /// falling back to writing to sysfs. This makes a misconfigured logind setup easy to spot.
+    ///
+    /// Defaults to `false`.
+    pub fn with_require_logind(mut self, require_logind: bool) -> Self {
+        self.device_options.require_logind = require_logind;
+        self
+    }
+
+    /// When enabled, the brightness is only read: no D-Bus connection is made, which makes
+    /// [`CalibrightBuilder::build`] faster and works without a system bus, e.g. for status bars.
+    /// Setting the brightness fails with [`CalibrightError::ReadOnly`].
+    ///
+    /// Defaults to `false`.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.device_options.read_only = read_only;
+        self
+    }
+
+    /// Use `connection` to reach logind, shared by all devices, instead of connecting to the
+    /// system bus. This allows e.g. testing against a mock logind service on another bus.
+    pub fn with_dbus_connection(mut self, connection: zbus::Connection) -> Self {
+        self.device_options.dbus_connection = Some(connection);
+        self
+    }
+
+    /// Reach logind under different D-Bus names, e.g. for elogind or another compatible service
+    /// on systems without systemd. The names are validated by [`CalibrightBuilder::build`].
+    ///
+    /// Defaults to `org.freedesktop.login1`, `org.freedesktop.login1.Session` and
+    /// `/org/freedesktop/login1/session/auto`.
+    pub fn with_logind_names(
+        mut self,
+        service: &'a str,
+        session_interface: &'a str,
+        session_path: &'a str,
+    ) -> Self {
+        self.logind_names = Some([service, session_interface, session_path]);
+        self
+    }
+
+    /// When enabled, [`Calibright::set_brightness`] adjusts each device by the given amount
+    /// relative to its current brightness, preserving the differences between devices.
+    ///
+    /// Defaults to `false` (all devices are set to the given absolute brightness).
+    pub fn with_relative_mode(mut self, relative_mode: bool) -> Self {
+        self.relative_mode = relative_mode;
+        self
+    }
+
+    /// How the brightness of the devices is combined by [`Calibright::get_brightness`], e.g.
+    /// [`Aggregation::Max`] to show the brightest monitor in a status bar.
+    /// Default is [`Aggregation::Mean`].
+    pub fn with_get_aggregation(mut self, get_aggregation: Aggregation) -> Self {
+        self.get_aggregation = get_aggregation;
+        self
+    }
+
+    /// How [`Calibright::set_brightness`] applies the brightness to the devices.
+    /// Default is [`SetAggregation::Uniform`].
+    pub fn with_set_aggregation(mut self, set_aggregation: SetAggregation) -> Self {
+        self.set_aggregation = set_aggregation;
+        self
+    }
+
+    /// Declare devices that control the same physical display (e.g. `intel_backlight` and
+    /// `acpi_video0`), by device name. Can be called multiple times for several groups.
+    ///
+    /// Writes still go to every member, but when reading the brightness only the first member
+    /// (in the given order) that was found is counted, so the display isn't counted twice.
+    pub fn with_device_group(mut self, device_names: &[&str]) -> Self {
+        self.device_groups
+            .push(device_names.iter().map(OsString::from).collect());
+        self
+    }
+
+    /// How brightness values outside of 0.0 to 1.0 passed to [`Calibright::set_brightness`] and
+    /// [`Calibright::adjust_brightness`] (after adding the delta) are handled.
+    /// Default is [`OverflowPolicy::Clamp`].
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.device_options.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Use other file names than the sysfs defaults for all devices, e.g. on boards whose drivers
+    /// don't follow the `/sys/class/backlight` conventions:
+    /// - `read`: the current brightness, instead of `actual_brightness` (or `brightness` for LEDs
+    ///   and amdgpu)
+    /// - `write`: the brightness to set, instead of `brightness` (a configured `write_file` takes
+    ///   precedence)
+    /// - `max`: the max brightness, instead of `max_brightness`
+    pub fn with_brightness_filenames(
+        mut self,
+        read: impl Into<OsString>,
+        write: impl Into<OsString>,
+        max: impl Into<OsString>,
+    ) -> Self {
+        self.device_options.read_file = Some(read.into());
+        self.device_options.write_file = Some(write.into());
+        self.device_options.max_file = Some(max.into());
+        self
+    }
+
+    /// Set the base delay between DDC/CI reads of all devices, in whole milliseconds from 1 to
+    /// 1000. It takes precedence over `ddcci_read_base_ms` of both the config file and a config
+    /// passed to [`CalibrightBuilder::with_config`], while `ddcci_sleep_multiplier` still applies.
+    pub fn with_ddc_base_delay(mut self, delay: Duration) -> Self {
+        self.device_options.ddc_base_delay = Some(delay);
+        self
+    }
+
+    /// The file system used to discover, read and write the devices, e.g. a [`MemoryFs`] to
+    /// test without real hardware. Writes through logind are not affected, see
+    /// [`CalibrightBuilder::with_dbus_connection`].
+    ///
+    /// Defaults to [`TokioFs`].
+    pub fn with_fs_provider(mut self, fs: impl FsProvider + 'static) -> Self {
+        self.device_options.fs = std::sync::Arc::new(fs);
+        self
+    }
+
+    /// How devices that can't be read are counted by [`Calibright::get_brightness`], e.g. to keep
+    /// a status bar from jumping while a DDC/CI monitor is busy.
+    /// Default is [`ReadErrorPolicy::Skip`].
+    pub fn with_read_error_policy(mut self, read_error_policy: ReadErrorPolicy) -> Self {
+        self.device_options.read_error_policy = read_error_policy;
+        self
+    }
+
+    /// Allow setting a raw brightness of 0, e.g. to turn off a keyboard backlight with
+    /// `set_brightness(0.0)`. By default the raw brightness is at least 1, so that the screen
+    /// never turns off completely.
+    pub fn with_allow_zero(mut self, allow_zero: bool) -> Self {
+        self.device_options.allow_zero = allow_zero;
+        self
+    }
+
+    /// Skip writing to a device if the new raw brightness is within `tolerance` of its cached raw
+    /// brightness (`0` only skips identical values), avoiding slow DDC writes that change nothing.
+    /// Disabled by default.
+    ///
+    /// The cache is only updated when the brightness is read or written by this instance, so a
+    /// change made elsewhere in the meantime can cause a needed write to be skipped. Reading the
+    /// brightness first (as [`Calibright::adjust_brightness`] does) avoids this.
+    pub fn with_skip_redundant_writes(mut self, tolerance: u32) -> Self {
+        self.device_options.skip_write_tolerance = Some(tolerance);
+        self
+    }
+
+    /// Read all devices every `refresh_interval` on a background task, so that
+    /// [`Calibright::get_brightness_cached`] stays up to date without blocking on slow reads.
+    /// Disabled by default.
+    pub fn with_background_refresh(mut self, refresh_interval: Duration) -> Self {
+        self.background_refresh = Some(refresh_interval);
+        self
+    }
+
+    /// Apply [`Calibright::set_brightness`] on a background task that only writes the latest
+    /// brightness, dropping the ones set while a write is still in progress, e.g. for a slider
+    /// controlling slow DDC/CI monitors. The last brightness set is always written.
+    /// Disabled by default.
+    ///
+    /// With this enabled, [`Calibright::set_brightness`] returns before the brightness is
+    /// written and write errors are only logged. Relative mode, [`SetAggregation::Preserve`] and
+    /// the other ways of setting the brightness aren't coalesced.
+    pub fn with_coalesced_writes(mut self, coalesced_writes: bool) -> Self {
+        self.coalesced_writes = coalesced_writes;
+        self
+    }
+
+    /// Record the raw brightness of each device in `state_file` after every
+    /// [`Calibright::set_brightness`], so that [`Calibright::restore_from_state`] can restore it
+    /// on startup, e.g. for drivers that don't retain the brightness across reboots.
+    pub fn with_state_file(mut self, state_file: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(state_file.into());
+        self
+    }
+
     #[cfg(feature = "watch")]
     #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
     /// Default poll_interval is 2 seconds.
@@ -75,190 +437,1587 @@ impl<'a> CalibrightBuilder<'a> {
         self
     }
 
+    #[cfg(feature = "watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+    /// Whether to watch for devices being added/removed. When disabled only the brightness files
+    /// of the devices found by [`CalibrightBuilder::build`] are watched, which avoids polling the
+    /// sysfs directories, but new devices are not picked up by [`Calibright::next`] (see
+    /// [`Calibright::refresh_devices`]).
+    ///
+    /// Defaults to `true`.
+    pub fn with_hotplug(mut self, hotplug: bool) -> Self {
+        self.hotplug = hotplug;
+        self
+    }
+
+    #[cfg(feature = "watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+    /// Follow the device with the given name: whenever [`Calibright::next`] sees its brightness
+    /// being changed externally, all other devices are set to the same calibrated brightness.
+    pub fn with_mirror_from(mut self, device_name: impl Into<OsString>) -> Self {
+        self.mirror_from = Some(device_name.into());
+        self
+    }
+
+    #[cfg(feature = "watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+    /// Call `on_change` with each change as it happens, as an alternative to awaiting
+    /// [`Calibright::next`] in a loop.
+    ///
+    /// The events of the watchers are received by a spawned task, which is stopped when the
+    /// returned [`Calibright`] is dropped. They are still passed on to [`Calibright::next`], so
+    /// that calling it picks up added/removed devices, but it doesn't return the brightness
+    /// changes that were already reported to `on_change`.
+    pub fn with_on_change(mut self, on_change: impl Fn(ChangeEvent) + Send + 'static) -> Self {
+        self.on_change = Some(Box::new(on_change));
+        self
+    }
+
     /// Returns the constructed [`Calibright`] instance.
-    pub async fn build(self) -> Result<Calibright> {
+    ///
+    /// Returns [`CalibrightError::NoDevices`] rather than an IO error if none of the subsystems
+    /// exist, e.g. `/sys/class/backlight` on a desktop without a backlight.
+    pub async fn build(mut self) -> Result<Calibright> {
+        // Only `calibration` and `root_scaling` are used, the rest are the valid defaults
+        self.display.validate()?;
+        // Validated even when they aren't used because of `with_config`, so a mistake isn't hidden
+        self.defaults.validate()?;
+        if !(self.smoothing_factor > 0.0 && self.smoothing_factor <= 1.0) {
+            return Err(CalibrightError::Other(format!(
+                "Invalid smoothing factor {}, expected a number greater than 0.0 and up to 1.0",
+                self.smoothing_factor
+            )));
+        }
+        if let Some(delay) = self.device_options.ddc_base_delay {
+            let base_ms = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX);
+            if !DDCCI_READ_BASE_MS_RANGE.contains(&base_ms) {
+                return Err(CalibrightError::Other(format!(
+                    "Invalid DDC/CI base delay {delay:?}, expected 1 to 1000 ms"
+                )));
+            }
+        }
+        if let Some([service, session_interface, session_path]) = self.logind_names {
+            self.device_options.logind_names =
+                LogindNames::new(service, session_interface, session_path)?;
+        }
+
         let config = match self.config {
             Some(config) => config,
-            None => CalibrightConfig::new().await?,
+            None => CalibrightConfig::new_with_defaults(&self.defaults).await?,
+        };
+
+        let device_regex = match (self.device_names, self.device_regex) {
+            (Some(device_names), _) => Regex::new(&format!(
+                "^({})$",
+                device_names
+                    .iter()
+                    .map(|device_name| regex::escape(device_name))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            ))?,
+            (None, Some(device_regex)) => Regex::new(device_regex)?,
+            (None, None) => Regex::new(
+                &std::env::var(ENV_DEVICE)
+                    .ok()
+                    .filter(|device_regex| !device_regex.is_empty())
+                    .unwrap_or_else(|| ".".into()),
+            )?,
+        };
+
+        let device_names = match self.device_names {
+            Some(device_names) => {
+                find_named_devices(
+                    device_names,
+                    &self.subsystems,
+                    &config,
+                    &self.device_options,
+                )
+                .await?
+            }
+            None => {
+                scan_device_names(
+                    &device_regex,
+                    &self.subsystems,
+                    &config,
+                    &self.device_options,
+                )
+                .await?
+            }
         };
 
-        Calibright::new(
-            Regex::new(self.device_regex)?,
+        let mut calibright = Calibright::new(
+            device_regex,
+            device_names,
+            self.subsystems,
             config,
+            self.device_options,
             #[cfg(feature = "watch")]
             self.poll_interval,
+            #[cfg(feature = "watch")]
+            self.hotplug,
         )
+        .await?;
+        calibright.relative_mode = self.relative_mode;
+        calibright.get_aggregation = self.get_aggregation;
+        calibright.set_aggregation = self.set_aggregation;
+        calibright.device_groups = self.device_groups;
+        calibright.display_calibration = self.display.calibration;
+        calibright.display_root_scaling = self.display.root_scaling;
+        calibright.smoothing_factor = self.smoothing_factor;
+        calibright.set_background_refresh(self.background_refresh);
+        calibright.set_coalesced_writes(self.coalesced_writes);
+        calibright.state_file = self.state_file;
+        #[cfg(feature = "watch")]
+        {
+            calibright.mirror_from = self.mirror_from;
+            if let Some(on_change) = self.on_change {
+                calibright.change_observer =
+                    Some(ChangeObserver::spawn(&mut calibright, on_change));
+            }
+        }
+
+        Ok(calibright)
+    }
+}
+
+/// Get the brightness of the devices matching `device_regex` once, see
+/// [`Calibright::get_brightness`].
+///
+/// Every call scans the devices and reads the config again, keep a [`Calibright`] around for
+/// repeated calls instead.
+pub async fn get_brightness(device_regex: &str) -> Result<f64> {
+    CalibrightBuilder::new()
+        .with_device_regex(device_regex)
+        .build()
+        .await?
+        .get_brightness()
+        .await
+}
+
+/// Set the brightness of the devices matching `device_regex` once, see
+/// [`Calibright::set_brightness`].
+///
+/// Every call scans the devices and reads the config again, keep a [`Calibright`] around for
+/// repeated calls instead.
+pub async fn set_brightness(device_regex: &str, brightness: f64) -> Result<()> {
+    CalibrightBuilder::new()
+        .with_device_regex(device_regex)
+        .build()
+        .await?
+        .set_brightness(brightness)
         .await
+}
+
+/// Scan the sysfs directories of `subsystems` for devices whose name matches `device_regex`.
+/// Entries without a `max_brightness` file (or alternate, see [`find_max_file`]) aren't devices
+/// and are skipped.
+async fn scan_device_names(
+    device_regex: &Regex,
+    subsystems: &[Subsystem],
+    config: &CalibrightConfig,
+    device_options: &DeviceOptions,
+) -> Result<Vec<(Subsystem, String)>> {
+    let fs = &*device_options.fs;
+    let mut device_names = Vec::new();
+    let mut subsystem_found = false;
+    for &subsystem in subsystems {
+        let entries = match fs.read_dir(Path::new(subsystem.path())).await {
+            Ok(entries) => entries,
+            // Common on desktops and VMs without a backlight
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!(
+                    "no {} subsystem found at {}",
+                    subsystem.as_str(),
+                    subsystem.path()
+                );
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        subsystem_found = true;
+        for device_name in entries {
+            #[cfg(feature = "i2c")]
+            if subsystem == Subsystem::I2c {
+                // I2C buses have no sysfs files, whether there is a monitor is only known once
+                // the device is created
+                let device_name = device_name.to_string_lossy();
+                if device_name.starts_with(I2C_PREFIX)
+                    && device_matches(device_regex, subsystem, &device_name, &*device_options.fs)
+                        .await
+                {
+                    device_names.push((subsystem, device_name.to_string()));
+                }
+                continue;
+            }
+            if !device_matches(
+                device_regex,
+                subsystem,
+                &device_name.to_string_lossy(),
+                &*device_options.fs,
+            )
+            .await
+            {
+                continue;
+            }
+            if !has_max_file(
+                subsystem,
+                &device_name.to_string_lossy(),
+                config,
+                device_options,
+            )
+            .await
+            {
+                debug!("skipping {device_name:?}, it has no max brightness file");
+                continue;
+            }
+            debug!(
+                "{:?} matched {}",
+                device_name.to_string_lossy().to_string(),
+                device_regex.as_str()
+            );
+
+            device_names.push((subsystem, device_name.to_string_lossy().to_string()));
+        }
     }
+    if !subsystem_found {
+        return Err(CalibrightError::NoDevices);
+    }
+
+    Ok(device_names)
 }
 
-#[cfg(not(feature = "watch"))]
-pub struct Calibright {
-    devices: HashMap<OsString, Device>,
+/// Find the subsystem of each of the named devices, failing if a device doesn't exist.
+async fn find_named_devices(
+    device_names: &[&str],
+    subsystems: &[Subsystem],
+    config: &CalibrightConfig,
+    device_options: &DeviceOptions,
+) -> Result<Vec<(Subsystem, String)>> {
+    let mut found = Vec::new();
+    for device_name in device_names {
+        let mut subsystem_found = None;
+        for &subsystem in subsystems {
+            #[cfg(feature = "i2c")]
+            if subsystem == Subsystem::I2c {
+                // I2C buses have no sysfs files to check for
+                let device_path = Path::new(subsystem.path()).join(device_name);
+                if device_options
+                    .fs
+                    .exists(&device_path)
+                    .await
+                    .unwrap_or(false)
+                {
+                    subsystem_found = Some(subsystem);
+                    break;
+                }
+                continue;
+            }
+            if has_max_file(subsystem, device_name, config, device_options).await {
+                subsystem_found = Some(subsystem);
+                break;
+            }
+        }
+        match subsystem_found {
+            Some(subsystem) => found.push((subsystem, device_name.to_string())),
+            None => {
+                return Err(CalibrightError::Other(format!(
+                    "Device {device_name} doesn't exist"
+                )))
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Whether the device has a file to read the max brightness from, see [`find_max_file`].
+async fn has_max_file(
+    subsystem: Subsystem,
+    device_name: &str,
+    config: &CalibrightConfig,
+    device_options: &DeviceOptions,
+) -> bool {
+    let connector = connector_name(
+        &*device_options.fs,
+        &Path::new(subsystem.sysfs_path()).join(device_name),
+    )
+    .await;
+    let device_config = config.get_device_config(&device_name.to_string(), connector.as_deref());
+    find_max_file(
+        &Path::new(subsystem.path()).join(device_name),
+        &device_config,
+        device_options,
+    )
+    .await
+    .is_some()
+}
+
+/// Whether `device_regex` matches the name of the device or of its DRM connector (e.g. `DP-1`).
+async fn device_matches(
+    device_regex: &Regex,
+    subsystem: Subsystem,
+    device_name: &str,
+    fs: &dyn FsProvider,
+) -> bool {
+    if device_regex.is_match(device_name) {
+        return true;
+    }
+    connector_name(fs, &Path::new(subsystem.sysfs_path()).join(device_name))
+        .await
+        .is_some_and(|connector| device_regex.is_match(&connector))
+}
+
+#[cfg(feature = "watch")]
+/// The paths of `event` that are entries directly in one of the `subsystems` directories, i.e.
+/// devices being added or removed.
+fn subsystem_entries<'a>(
+    subsystems: &[Subsystem],
+    event: &'a notify::Event,
+) -> Vec<(Subsystem, &'a PathBuf)> {
+    event
+        .paths
+        .iter()
+        .filter_map(|p| {
+            subsystems
+                .iter()
+                .find(|subsystem| p.parent() == Some(Path::new(subsystem.path())))
+                .map(|&subsystem| (subsystem, p))
+        })
+        .collect()
+}
+
+#[cfg(feature = "watch")]
+/// Adds a change to `changes` for each of the `devices` whose brightness was changed by something
+/// else according to the modify `event`, as soon as it is observed. Changes made by us and repeated
+/// reports of a change within `poll_interval` are left out.
+async fn brightness_changes(
+    event: &notify::Event,
+    devices: &mut HashMap<OsString, Device>,
+    subsystems: &[Subsystem],
+    read_file: Option<&OsStr>,
+    poll_interval: Duration,
+    changes: &mut impl Extend<ChangeEvent>,
+) {
+    let brightness_paths: Vec<&PathBuf> = event
+        .paths
+        .iter()
+        .filter(|&p| {
+            p.ends_with(FILE_BRIGHTNESS)
+                || p.ends_with(FILE_BRIGHTNESS_AMD)
+                || p.ends_with(FILE_BRIGHTNESS_HW_CHANGED)
+                || read_file.is_some_and(|read_file| p.ends_with(read_file))
+        })
+        .collect();
+    if !brightness_paths.is_empty() {
+        for brightness_path in brightness_paths {
+            let Some(device) = brightness_path
+                .parent()
+                .and_then(Path::file_name)
+                .and_then(|file_name| devices.get_mut(file_name))
+            else {
+                continue;
+            };
+            // Only the file we read from and hardware changes are relevant. For amdgpu the read
+            // file is also the file we write to, so our own writes are suppressed below.
+            if *brightness_path != device.read_brightness_file
+                && Some(brightness_path) != device.hw_changed_file.as_ref()
+            {
+                continue;
+            }
+            // The poll and inotify watchers can both report the same change, so only report a
+            // value once per poll interval.
+            if device.get_last_set_ago() > poll_interval
+                && device.observe_change(poll_interval).await
+            {
+                changes.extend([ChangeEvent::BrightnessChanged {
+                    device: device.device_name.clone(),
+                }]);
+            }
+        }
+    } else {
+        // A change of a whole subsystem, e.g. reported over D-Bus, so check all its devices
+        let changed_subsystems: Vec<Subsystem> = subsystems
+            .iter()
+            .filter(|subsystem| event.paths.iter().any(|p| p == Path::new(subsystem.path())))
+            .copied()
+            .collect();
+        for device in devices.values_mut() {
+            if changed_subsystems.contains(&device.subsystem)
+                && device.get_last_set_ago() > poll_interval
+                && device.observe_change(poll_interval).await
+            {
+                changes.extend([ChangeEvent::BrightnessChanged {
+                    device: device.device_name.clone(),
+                }]);
+            }
+        }
+    }
+}
+
+/// Warn if both an ACPI firmware backlight and a native backlight were matched, as they usually
+/// control the same panel, which would then be counted twice in the average brightness.
+fn warn_duplicate_panel_controls(device_names: &[(Subsystem, String)]) {
+    if let Some((firmware, native)) = duplicate_panel_controls(device_names) {
+        warn!(
+            "{firmware:?} and {native:?} likely control the same panel, use a more specific \
+             device regex or group them with `CalibrightBuilder::with_device_group`"
+        );
+    }
+}
+
+/// The ACPI firmware and native backlights, if both were matched.
+fn duplicate_panel_controls(
+    device_names: &[(Subsystem, String)],
+) -> Option<(Vec<&str>, Vec<&str>)> {
+    let (firmware, native): (Vec<_>, Vec<_>) = device_names
+        .iter()
+        .filter(|(subsystem, name)| {
+            *subsystem == Subsystem::Backlight && !name.starts_with(DDCCI_PREFIX)
+        })
+        .map(|(_, name)| name.as_str())
+        .partition(|name| name.starts_with(ACPI_VIDEO_PREFIX));
+    (!firmware.is_empty() && !native.is_empty()).then_some((firmware, native))
+}
+
+/// Create the devices with the given names. Devices that can't be created are skipped.
+async fn create_devices(
+    device_names: &[(Subsystem, String)],
+    config: &CalibrightConfig,
+    device_options: &DeviceOptions,
+) -> Result<Vec<Device>> {
+    let device_list = join_all(device_names.iter().map(|(subsystem, device_name)| {
+        Device::new(*subsystem, device_name, config, device_options.clone())
+    }))
+    .await;
+    let mut devices = Vec::new();
+    for device in device_list {
+        match device {
+            Ok(device) => devices.push(device),
+            // Don't hide a missing logind behind devices silently being dropped
+            Err(e @ CalibrightError::LogindRequired(_)) => return Err(e),
+            Err(e) => {
+                debug!("{e}");
+            }
+        }
+    }
+
+    Ok(devices)
+}
+
+/// The devices whose raw brightness differs between `old` and `new`, as `(name, old, new)`.
+fn diff_snapshots(old: &[DeviceSnapshot], new: &[DeviceSnapshot]) -> Vec<(OsString, f64, f64)> {
+    new.iter()
+        .filter_map(|new| {
+            let old = old.iter().find(|old| old.name == new.name)?;
+            (old.raw != new.raw).then(|| (new.name.clone(), old.percent, new.percent))
+        })
+        .collect()
+}
+
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+/// A change reported by [`Calibright::next`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChangeEvent {
+    /// The brightness of the device was changed by something else, e.g. another program or a
+    /// brightness key handled by the firmware.
+    BrightnessChanged { device: OsString },
+    /// A matching device was added.
+    DeviceAdded { device: OsString },
+    /// A device was removed.
+    DeviceRemoved { device: OsString },
 }
 
 #[cfg(feature = "watch")]
+impl ChangeEvent {
+    /// The name of the device that changed.
+    pub fn device(&self) -> &OsStr {
+        match self {
+            ChangeEvent::BrightnessChanged { device }
+            | ChangeEvent::DeviceAdded { device }
+            | ChangeEvent::DeviceRemoved { device } => device,
+        }
+    }
+}
+
+/// The devices that were added and removed by [`Calibright::refresh_devices`].
+#[derive(Clone, Debug, Default)]
+pub struct RefreshReport {
+    /// Names of the newly matched devices, sorted.
+    pub added: Vec<OsString>,
+    /// Names of the devices that no longer exist, sorted.
+    pub removed: Vec<OsString>,
+}
+
+impl RefreshReport {
+    /// Whether any device was added or removed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The brightness reported by [`Calibright::get_brightness_detailed`], together with how many
+/// devices it is based on.
+#[derive(Clone, Copy, Debug)]
+pub struct AveragedBrightness {
+    /// The combined brightness as [`Calibright::get_brightness`] reports it, in range 0.0 to 1.0
+    /// (inclusive).
+    pub value: f64,
+    /// Number of devices that could be read.
+    pub contributing: usize,
+    /// Number of matched devices.
+    pub total: usize,
+}
+
+/// The brightness reported by [`Calibright::get_brightness_with_deadline`], together with the
+/// errors of the devices that couldn't be read.
+#[derive(Debug)]
+pub struct PartialBrightness {
+    /// The combined brightness of the devices that could be read as
+    /// [`Calibright::get_brightness`] reports it, in range 0.0 to 1.0 (inclusive).
+    pub value: f64,
+    /// The error of each device that couldn't be read, [`CalibrightError::TimedOut`] for those
+    /// that didn't respond before the deadline.
+    pub errors: Vec<CalibrightError>,
+}
+
 pub struct Calibright {
     devices: HashMap<OsString, Device>,
+    relative_mode: bool,
+    get_aggregation: Aggregation,
+    set_aggregation: SetAggregation,
+    device_groups: Vec<Vec<OsString>>,
+    display_calibration: [f64; 2],
+    display_root_scaling: f64,
+    smoothing_factor: f64,
+    /// The last brightness reported by [`Calibright::get_brightness`], with smoothing.
+    smoothed_brightness: Option<f64>,
+    device_set_changed: AtomicBool,
     device_regex: Regex,
+    subsystems: Vec<Subsystem>,
     config: CalibrightConfig,
-    _poll_watcher: PollWatcher,
+    device_options: DeviceOptions,
+    background_refresh: Option<(Duration, BackgroundRefresh)>,
+    coalesced_writer: Option<CoalescedWriter>,
+    state_file: Option<PathBuf>,
+    #[cfg(feature = "watch")]
+    _device_watcher: Option<DeviceWatcher>,
+    #[cfg(feature = "watch")]
     inotify_watcher: INotifyWatcher,
-    rx: Receiver<notify::Result<notify::Event>>,
+    #[cfg(feature = "dbus-watch")]
+    _dbus_watcher: crate::dbus_watcher::DBusWatcher,
+    #[cfg(feature = "watch")]
+    tx: EventSender,
+    #[cfg(feature = "watch")]
+    rx: EventReceiver,
+    #[cfg(feature = "watch")]
+    pending_event: Option<notify::Event>,
+    /// Changes of a handled event that weren't returned by [`Calibright::next`] yet.
+    #[cfg(feature = "watch")]
+    pending_changes: std::collections::VecDeque<ChangeEvent>,
+    #[cfg(feature = "watch")]
     poll_interval: Duration,
+    #[cfg(feature = "watch")]
+    mirror_from: Option<OsString>,
+    #[cfg(feature = "watch")]
+    change_observer: Option<ChangeObserver>,
+    #[cfg(feature = "poll-watch")]
+    poll_snapshot: Option<Vec<DeviceSnapshot>>,
 }
 
 impl Calibright {
     pub(crate) async fn new(
         device_regex: Regex,
+        device_names: Vec<(Subsystem, String)>,
+        subsystems: Vec<Subsystem>,
         config: CalibrightConfig,
+        device_options: DeviceOptions,
         #[cfg(feature = "watch")] poll_interval: Duration,
+        #[cfg(feature = "watch")] hotplug: bool,
     ) -> Result<Self> {
-        let mut sysfs_paths = read_dir(DEVICES_PATH).await?;
-
-        let mut device_names = Vec::new();
-        while let Some(sysfs_path) = sysfs_paths.next_entry().await? {
-            let device_name = sysfs_path.file_name();
-            if device_regex.is_match(&device_name.to_string_lossy()) {
-                debug!(
-                    "{:?} matched {}",
-                    device_name.to_string_lossy().to_string(),
-                    device_regex.as_str()
-                );
-
-                device_names.push(device_name.to_string_lossy().to_string());
-            }
-        }
+        warn_duplicate_panel_controls(&device_names);
+        let devices = create_devices(&device_names, &config, &device_options).await?;
 
         let mut device_map = HashMap::new();
-        let device_list =
-            join_all(device_names.iter().map(|device_name| {
-                Device::new(device_name, config.get_device_config(device_name))
-            }))
-            .await;
-        let device_list = device_list.iter().filter_map(|device| match device {
-            Ok(device) => Some(device.to_owned()),
-            Err(e) => {
-                debug!("{e}");
-                None
-            }
-        });
 
-        #[cfg(not(feature = "watch"))]
-        {
-            for device in device_list {
-                device_map.insert(device.device_name.clone(), device);
-            }
+        #[cfg(feature = "watch")]
+        let (_device_watcher, mut inotify_watcher, tx, rx) = pseudo_fs_watcher(
+            &subsystems
+                .iter()
+                .map(|subsystem| subsystem.path())
+                .collect::<Vec<_>>(),
+            poll_interval,
+            // The subsystem directories of e.g. a `MemoryFs` don't exist to be polled
+            hotplug && device_options.fs.can_watch(),
+        )?;
+        #[cfg(feature = "dbus-watch")]
+        let _dbus_watcher = crate::dbus_watcher::DBusWatcher::new(&subsystems, tx.clone());
 
-            Ok(Calibright {
-                devices: device_map,
-            })
+        for device in devices {
+            #[cfg(feature = "watch")]
+            watch_device(&mut inotify_watcher, &device)?;
+            device_map.insert(device.device_name.clone(), device);
         }
 
-        #[cfg(feature = "watch")]
-        {
-            let (_poll_watcher, mut inotify_watcher, rx) =
-                pseudo_fs_watcher(DEVICES_PATH, poll_interval)?;
+        Ok(Calibright {
+            devices: device_map,
+            relative_mode: false,
+            get_aggregation: Aggregation::default(),
+            set_aggregation: SetAggregation::default(),
+            device_groups: Vec::new(),
+            display_calibration: [0.0, 1.0],
+            display_root_scaling: 1.0,
+            smoothing_factor: 1.0,
+            smoothed_brightness: None,
+            device_set_changed: AtomicBool::new(false),
+            device_regex,
+            subsystems,
+            config,
+            device_options,
+            background_refresh: None,
+            coalesced_writer: None,
+            state_file: None,
+            #[cfg(feature = "watch")]
+            _device_watcher,
+            #[cfg(feature = "watch")]
+            inotify_watcher,
+            #[cfg(feature = "dbus-watch")]
+            _dbus_watcher,
+            #[cfg(feature = "watch")]
+            tx,
+            #[cfg(feature = "watch")]
+            rx,
+            #[cfg(feature = "watch")]
+            pending_event: None,
+            #[cfg(feature = "watch")]
+            pending_changes: Default::default(),
+            #[cfg(feature = "watch")]
+            poll_interval,
+            #[cfg(feature = "watch")]
+            mirror_from: None,
+            #[cfg(feature = "watch")]
+            change_observer: None,
+            #[cfg(feature = "poll-watch")]
+            poll_snapshot: None,
+        })
+    }
 
-            for device in device_list {
-                let watch_path = device.read_brightness_file.to_path_buf();
-                inotify_watcher.watch(&watch_path, notify::RecursiveMode::NonRecursive)?;
-                device_map.insert(device.device_name.clone(), device);
+    #[cfg(feature = "watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+    /// Wait for a device to be added/removed or for brightness to be changed, and return what
+    /// changed. An event changing several devices is returned as one change per device by
+    /// consecutive calls.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe and can be used as a branch of `tokio::select!`.
+    /// If the returned future is dropped before completion, no events are lost: events that have
+    /// not yet been received stay buffered in the channel, changes are queued as soon as they
+    /// happen, and an event that was only partially handled is handled again by the next call
+    /// without reporting the changes that were already queued.
+    pub async fn next(&mut self) -> Result<ChangeEvent> {
+        loop {
+            if let Some(change) = self.pending_changes.pop_front() {
+                return Ok(change);
             }
-
-            Ok(Calibright {
-                devices: device_map,
-                device_regex,
-                config,
-                _poll_watcher,
-                inotify_watcher,
-                rx,
-                poll_interval,
-            })
+            let event = match self.pending_event.take() {
+                Some(event) => event,
+                // The watchers keep a sender, so the channel only ends when it was shut down
+                None => match self.rx.recv().await {
+                    Some(res) => res?,
+                    None => return Err(CalibrightError::Other("Watching was shut down".into())),
+                },
+            };
+            // Hold on to the event until it has been fully handled, so that it is retried if
+            // this future is dropped while handling it.
+            self.pending_event = Some(event.clone());
+            let res = self.queue_changes(&event).await;
+            self.pending_event = None;
+            res?;
         }
     }
 
     #[cfg(feature = "watch")]
     #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
-    /// Wait for a device to be added/removed or for brightness to be changed.
-    pub async fn next(&mut self) -> Result<()> {
-        use std::path::{Path, PathBuf};
-
-        while let Some(res) = self.rx.recv().await {
-            let mut change_occurred = false;
-            let event = res?;
-            debug!("{:?}", event);
-            let depth1_paths: Vec<&PathBuf> = event
-                .paths
-                .iter()
-                .filter(|&p| p.parent() == Some(Path::new(DEVICES_PATH)))
-                .collect();
-            let brightness_paths: Vec<&PathBuf> = event
-                .paths
-                .iter()
-                .filter(|&p| p.ends_with(FILE_BRIGHTNESS) || p.ends_with(FILE_BRIGHTNESS_AMD))
-                .collect();
-            if event.kind.is_create() && !depth1_paths.is_empty() {
-                for path in depth1_paths {
-                    if let Some(file_name) = path.file_name() {
-                        let device_name = file_name.to_string_lossy().to_string();
-                        debug!("New device {:?}", device_name);
-                        if self.devices.contains_key(file_name) {
-                            // We already know about this device, so no need to create a new `Device`
-                            debug!("New device {:?}, already known", path);
-                            continue;
-                        }
-                        if self.device_regex.is_match(&device_name) {
-                            debug!("{:?} matched {}", device_name, self.device_regex.as_str());
-                            let new_device = Device::new(
-                                &device_name,
-                                self.config.get_device_config(&device_name),
-                            )
-                            .await?;
-                            let watch_path = new_device.read_brightness_file.clone();
-                            self.inotify_watcher
-                                .watch(&watch_path, notify::RecursiveMode::NonRecursive)?;
-                            self.devices
-                                .insert(new_device.device_name.clone(), new_device);
-                            change_occurred = true;
-                        }
-                    }
-                }
-            } else if event.kind.is_remove() && !depth1_paths.is_empty() {
-                for path in depth1_paths {
-                    if let Some(file_name) = path.file_name() {
-                        debug!("Remove {}", path.display());
-                        if let Some(old_device) = self.devices.remove(file_name) {
-                            debug!("Removed {}", old_device.read_brightness_file.display());
-                            self.inotify_watcher
-                                .unwatch(&old_device.read_brightness_file)?;
-                            change_occurred = true;
-                        }
+    /// Like [`Calibright::next`], but stops waiting once `shutdown` completes.
+    ///
+    /// Returns the change, or `None` if `shutdown` completed first.
+    pub async fn next_or_shutdown<F>(&mut self, shutdown: F) -> Result<Option<ChangeEvent>>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        use futures_util::future::{select, Either};
+
+        let next = std::pin::pin!(self.next());
+        let shutdown = std::pin::pin!(shutdown);
+        match select(next, shutdown).await {
+            Either::Left((res, _)) => res.map(Some),
+            Either::Right(_) => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+    /// Stop all watchers and discard the events that weren't handled yet, e.g. for a deterministic
+    /// teardown of a daemon. Afterwards [`Calibright::next`] returns an error instead of waiting,
+    /// while the brightness can still be read and set.
+    pub fn shutdown(&mut self) -> Result<()> {
+        self._device_watcher = None;
+        self.change_observer = None;
+        for device in self.devices.values() {
+            unwatch_device(&mut self.inotify_watcher, device)?;
+        }
+        #[cfg(feature = "dbus-watch")]
+        self._dbus_watcher.stop();
+        self.rx.close();
+        let mut discarded = 0;
+        while self.rx.try_recv().is_ok() {
+            discarded += 1;
+        }
+        self.pending_event = None;
+        self.pending_changes.clear();
+        debug!("shut down, discarded {discarded} pending events");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+    /// Change how often the devices are polled for being added/removed, e.g. to poll faster while
+    /// the user is adjusting the brightness and slower when idle.
+    ///
+    /// Events that were already received are kept, as the watchers keep sending to the same
+    /// channel.
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) -> Result<()> {
+        let paths: Vec<&str> = self
+            .subsystems
+            .iter()
+            .map(|subsystem| subsystem.path())
+            .collect();
+        // The new watcher is created before the old one is dropped, so no change is missed
+        if self._device_watcher.is_some() {
+            self._device_watcher = Some(device_watcher(&paths, poll_interval, self.tx.clone())?);
+        }
+        self.poll_interval = poll_interval;
+
+        Ok(())
+    }
+
+    #[cfg(all(test, feature = "watch"))]
+    /// Returns the changes that should be reported for the event.
+    async fn handle_event(&mut self, event: &notify::Event) -> Result<Vec<ChangeEvent>> {
+        self.queue_changes(event).await?;
+        Ok(self.pending_changes.drain(..).collect())
+    }
+
+    #[cfg(feature = "watch")]
+    /// Queue the changes that should be reported for the event in `pending_changes`.
+    ///
+    /// Each change is queued together with the state change it reports, so handling the event
+    /// again after being dropped part way doesn't report a change twice or lose one.
+    async fn queue_changes(&mut self, event: &notify::Event) -> Result<()> {
+        debug!("{:?}", event);
+        let subsystem_entries = subsystem_entries(&self.subsystems, event);
+        if event.kind.is_create() && !subsystem_entries.is_empty() {
+            for (subsystem, path) in subsystem_entries {
+                if let Some(file_name) = path.file_name() {
+                    let device_name = file_name.to_string_lossy().to_string();
+                    debug!("New device {:?}", device_name);
+                    if self.devices.contains_key(file_name) {
+                        // We already know about this device, so no need to create a new `Device`
+                        debug!("New device {:?}, already known", path);
+                        continue;
+                    }
+                    if device_matches(
+                        &self.device_regex,
+                        subsystem,
+                        &device_name,
+                        &*self.device_options.fs,
+                    )
+                    .await
+                    {
+                        debug!("{:?} matched {}", device_name, self.device_regex.as_str());
+                        let new_device = Device::new(
+                            subsystem,
+                            &device_name,
+                            &self.config,
+                            self.device_options.clone(),
+                        )
+                        .await?;
+                        watch_device(&mut self.inotify_watcher, &new_device)?;
+                        self.pending_changes.push_back(ChangeEvent::DeviceAdded {
+                            device: new_device.device_name.clone(),
+                        });
+                        self.devices
+                            .insert(new_device.device_name.clone(), new_device);
+                        self.device_set_changed.store(true, Ordering::Relaxed);
+                        self.restart_background_tasks();
                     }
                 }
-            } else if event.kind.is_modify() && !brightness_paths.is_empty() {
-                for brightness_path in brightness_paths {
-                    if let Some(path) = brightness_path.parent() {
-                        if let Some(file_name) = path.file_name() {
-                            if let Some(device) = self.devices.get(file_name) {
-                                if device.get_last_set_ago() > self.poll_interval {
-                                    change_occurred = true;
-                                }
-                            }
-                        }
+            }
+        } else if event.kind.is_remove() && !subsystem_entries.is_empty() {
+            let mut removed = false;
+            for (_, path) in subsystem_entries {
+                if let Some(file_name) = path.file_name() {
+                    debug!("Remove {}", path.display());
+                    if let Some(old_device) = self.devices.remove(file_name) {
+                        debug!("Removed {}", old_device.read_brightness_file.display());
+                        unwatch_device(&mut self.inotify_watcher, &old_device)?;
+                        self.pending_changes.push_back(ChangeEvent::DeviceRemoved {
+                            device: old_device.device_name,
+                        });
+                        removed = true;
                     }
                 }
             }
-            if change_occurred {
-                return Ok(());
+            if removed {
+                self.device_set_changed.store(true, Ordering::Relaxed);
+                self.restart_background_tasks();
+            }
+        } else if event.kind.is_modify() {
+            let queued = self.pending_changes.len();
+            brightness_changes(
+                event,
+                &mut self.devices,
+                &self.subsystems,
+                self.device_options.read_file.as_deref(),
+                self.poll_interval,
+                &mut self.pending_changes,
+            )
+            .await;
+            let mirror = self
+                .pending_changes
+                .iter()
+                .skip(queued)
+                .any(|change| self.mirror_from.as_deref() == Some(change.device()));
+            if mirror {
+                self.mirror().await;
             }
         }
-        Err(CalibrightError::Other("Nothing to watch".into()))
+        Ok(())
     }
 
-    /// Get the average screen brightness based on the calibration settings.
-    /// Brightness is in range 0.0 to 1.0 (inclusive).
+    #[cfg(feature = "watch")]
+    /// Set all other devices to the brightness of the device set by
+    /// [`CalibrightBuilder::with_mirror_from`].
+    ///
+    /// The writes are marked as our own, so they don't show up as changes and can't loop back.
+    async fn mirror(&mut self) {
+        let Some(primary_name) = &self.mirror_from else {
+            return;
+        };
+        let Some(primary) = self.devices.get_mut(primary_name) else {
+            return;
+        };
+        let brightness = match primary.get_brightness().await {
+            Ok(brightness) => brightness,
+            Err(e) => {
+                debug!("not mirroring {:?}: {e}", primary_name);
+                return;
+            }
+        };
+        debug!("mirroring {:?} at {brightness}", primary_name);
+        let results = join_all(
+            self.devices
+                .iter_mut()
+                .filter(|(name, _)| *name != primary_name)
+                .map(|(_, device)| device.set_brightness(brightness)),
+        )
+        .await;
+        for result in results {
+            if let Err(e) = result {
+                debug!("failed to mirror brightness: {e}");
+            }
+        }
+    }
+
+    /// Start, change the interval of or (with `None`) stop reading all devices on a background
+    /// task. See [`CalibrightBuilder::with_background_refresh`].
+    pub fn set_background_refresh(&mut self, refresh_interval: Option<Duration>) {
+        self.background_refresh = refresh_interval.map(|refresh_interval| {
+            (
+                refresh_interval,
+                BackgroundRefresh::spawn(
+                    self.devices.values().cloned().collect(),
+                    refresh_interval,
+                ),
+            )
+        });
+    }
+
+    /// Enable or disable applying [`Calibright::set_brightness`] on a background task. See
+    /// [`CalibrightBuilder::with_coalesced_writes`].
+    pub fn set_coalesced_writes(&mut self, coalesced_writes: bool) {
+        self.coalesced_writer = coalesced_writes
+            .then(|| CoalescedWriter::spawn(self.devices.values().cloned().collect()));
+    }
+
+    /// Pick up added/removed devices in the background refresh, coalesced writes and change
+    /// observer, if enabled.
+    fn restart_background_tasks(&mut self) {
+        #[cfg(feature = "watch")]
+        if let Some(change_observer) = &self.change_observer {
+            change_observer.set_devices(self.devices.clone());
+        }
+        let refresh_interval = self
+            .background_refresh
+            .as_ref()
+            .map(|(refresh_interval, _)| *refresh_interval);
+        if refresh_interval.is_some() {
+            self.set_background_refresh(refresh_interval);
+        }
+        if self.coalesced_writer.is_some() {
+            self.set_coalesced_writes(true);
+        }
+    }
+
+    /// Scan for devices again, adding newly matched devices and dropping those that no longer
+    /// exist. This is useful to pick up hotplugged devices without the `watch` feature.
+    pub async fn refresh_devices(&mut self) -> Result<RefreshReport> {
+        let device_names = scan_device_names(
+            &self.device_regex,
+            &self.subsystems,
+            &self.config,
+            &self.device_options,
+        )
+        .await?;
+
+        let mut report = RefreshReport::default();
+        let stale: Vec<OsString> = self
+            .devices
+            .keys()
+            .filter(|name| {
+                !device_names
+                    .iter()
+                    .any(|(_, found)| *name == found.as_str())
+            })
+            .cloned()
+            .collect();
+        for name in stale {
+            if let Some(_old_device) = self.devices.remove(&name) {
+                debug!("Removed {:?}", name);
+                #[cfg(feature = "watch")]
+                unwatch_device(&mut self.inotify_watcher, &_old_device)?;
+                report.removed.push(name);
+            }
+        }
+
+        let new_device_names: Vec<(Subsystem, String)> = device_names
+            .into_iter()
+            .filter(|(_, name)| !self.devices.contains_key(OsStr::new(name)))
+            .collect();
+        for device in create_devices(&new_device_names, &self.config, &self.device_options).await? {
+            debug!("New device {:?}", device.device_name);
+            #[cfg(feature = "watch")]
+            watch_device(&mut self.inotify_watcher, &device)?;
+            report.added.push(device.device_name.clone());
+            self.devices.insert(device.device_name.clone(), device);
+        }
+
+        report.added.sort();
+        report.removed.sort();
+        if !report.is_empty() {
+            self.device_set_changed.store(true, Ordering::Relaxed);
+            self.restart_background_tasks();
+        }
+
+        Ok(report)
+    }
+
+    /// The regular expression used to match device names.
+    pub fn device_regex(&self) -> &str {
+        self.device_regex.as_str()
+    }
+
+    /// The sysfs classes that are scanned for devices.
+    pub fn subsystems(&self) -> &[Subsystem] {
+        &self.subsystems
+    }
+
+    /// The config the devices were resolved from.
+    pub fn config(&self) -> &CalibrightConfig {
+        &self.config
+    }
+
+    /// Whether devices were added or removed (by [`Calibright::next`] or
+    /// [`Calibright::refresh_devices`]) since the last call, e.g. for a status bar that only
+    /// needs to update its layout when the number of monitors changes.
+    pub fn device_set_changed(&self) -> bool {
+        self.device_set_changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Names of the devices that matched the device regex, sorted.
+    pub fn matched_device_names(&self) -> Vec<&OsStr> {
+        let mut names: Vec<&OsStr> = self.devices.keys().map(OsString::as_os_str).collect();
+        names.sort();
+
+        names
+    }
+
+    /// Device override sections of the config that matched no device (neither by device name nor
+    /// by connector name), sorted, e.g. to point out typos in the config.
+    pub fn unused_overrides(&self) -> Vec<String> {
+        let mut unused: Vec<String> = self
+            .config
+            .override_names()
+            .filter(|override_name| {
+                !self.devices.iter().any(|(name, device)| {
+                    name.as_os_str() == OsStr::new(override_name)
+                        || device.connector.as_deref() == Some(*override_name)
+                })
+            })
+            .map(str::to_owned)
+            .collect();
+        unused.sort();
+
+        unused
+    }
+
+    /// The configured alias (human readable name) of the device with the given name.
+    pub fn device_alias(&self, name: &OsStr) -> Option<&str> {
+        self.devices.get(name).and_then(Device::alias)
+    }
+
+    /// The name of the device with the given configured alias.
+    pub fn device_name_by_alias(&self, alias: &str) -> Option<&OsStr> {
+        self.devices
+            .iter()
+            .find(|(_, device)| device.alias() == Some(alias))
+            .map(|(name, _)| name.as_os_str())
+    }
+
+    /// Revert the device with the given name to the global config, discarding its override.
+    /// Returns `false` if there is no such device.
+    pub fn reset_device_config(&mut self, name: &OsStr) -> bool {
+        match self.devices.get_mut(name) {
+            Some(device) => {
+                device.set_config(self.config.global().clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the brightness of any device can likely be changed, e.g. to gray out controls
+    /// rather than failing when setting the brightness.
+    ///
+    /// This is determined when the devices are discovered: a device can be written if logind is
+    /// reachable over D-Bus, or if its sysfs brightness file is writable by the current user.
+    pub fn can_write(&self) -> bool {
+        self.devices.values().any(Device::can_write)
+    }
+
+    /// Whether the brightness of the device with the given name can likely be changed.
+    /// See [`Calibright::can_write`].
+    pub fn device_can_write(&self, name: &OsStr) -> Option<bool> {
+        self.devices.get(name).map(Device::can_write)
+    }
+
+    /// The path of the logind session used to set the brightness of the device with the given
+    /// name, e.g. to debug why writes fall back to sysfs. `Some(None)` if logind isn't reachable.
+    ///
+    /// The path ends in `/auto` if the caller's own session could be resolved by logind,
+    /// otherwise it is the session that was looked up explicitly.
+    pub fn device_session_path(&self, name: &OsStr) -> Option<Option<String>> {
+        self.devices.get(name).map(Device::session_path)
+    }
+
+    /// The DRM connector (e.g. `DP-1`) of the device with the given name, which unlike DDC/CI
+    /// device names is stable across reboots. `Some(None)` if the connector isn't known.
+    pub fn device_connector(&self, name: &OsStr) -> Option<Option<&str>> {
+        self.devices
+            .get(name)
+            .map(|device| device.connector.as_deref())
+    }
+
+    /// How many times the last read of the device with the given name had to be retried, e.g. to
+    /// tune the ddcci settings. `None` if there is no such device.
+    pub fn device_last_read_retries(&self, name: &OsStr) -> Option<u32> {
+        self.devices.get(name).map(Device::last_read_retries)
+    }
+
+    /// Check that the files each device is read from and written to exist and can be accessed,
+    /// e.g. to report a misconfigured `write_file` or `max_file` override up front instead of
+    /// when the brightness is first set. Returns the problems sorted by device name.
+    pub async fn validate_devices(
+        &self,
+    ) -> std::result::Result<(), Vec<(OsString, CalibrightError)>> {
+        let results = join_all(
+            self.devices
+                .iter()
+                .map(|(name, device)| async move { (name.clone(), device.check_files().await) }),
+        )
+        .await;
+        let mut problems: Vec<(OsString, CalibrightError)> = results
+            .into_iter()
+            .filter_map(|(name, result)| result.err().map(|e| (name, e)))
+            .collect();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        problems.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Err(problems)
+    }
+
+    /// What the device with the given name supports, e.g. to only offer turning off the backlight
+    /// where it is possible. `None` if there is no such device.
+    pub fn device_capabilities(&self, name: &OsStr) -> Option<Capabilities> {
+        self.devices.get(name).map(Device::capabilities)
+    }
+
+    /// The [`BacklightScale`] reported by the driver of the device with the given name, e.g. to
+    /// pick a `root_scaling`. `None` if there is no such device or the driver doesn't report it.
+    pub fn device_scale(&self, name: &OsStr) -> Option<BacklightScale> {
+        self.devices.get(name).and_then(Device::scale)
+    }
+
+    /// Get the resolved [`DeviceConfig`] (either the global config or a device override) for the
+    /// device with the given name.
+    pub fn device_config(&self, name: &OsStr) -> Option<&DeviceConfig> {
+        self.devices.get(name).map(Device::config)
+    }
+
+    /// The resolved [`DeviceConfig`] of every device, i.e. after merging the global config, the
+    /// `[ddcci]` section and the device overrides, e.g. to debug which values are in effect.
+    pub fn effective_config(&self) -> HashMap<OsString, DeviceConfig> {
+        self.devices
+            .iter()
+            .map(|(name, device)| (name.clone(), device.config().clone()))
+            .collect()
+    }
+
+    /// The `calibration` window of the device with the given name, as percents (0.0..=1.0) that
+    /// brightness `0.0` and `1.0` are mapped to.
+    pub fn device_calibration_window(&self, name: &OsStr) -> Option<[f64; 2]> {
+        self.devices.get(name).map(Device::calibration_window)
+    }
+
+    /// How the brightness of the device with the given name was last written by this instance,
+    /// e.g. to tell whether writes fell back from logind to sysfs.
+    pub fn last_write_method(&self, name: &OsStr) -> Option<WriteMethod> {
+        self.devices.get(name).and_then(Device::last_write_method)
+    }
+
+    /// When the brightness of the device with the given name was last set by this instance,
+    /// or when the device was discovered if it was never set.
+    pub fn last_set_at(&self, name: &OsStr) -> Option<std::time::Instant> {
+        self.devices.get(name).map(Device::get_last_set_at)
+    }
+
+    /// How long ago the brightness of the device with the given name was last set by this
+    /// instance, or when the device was discovered if it was never set.
+    pub fn last_set_ago(&self, name: &OsStr) -> Option<Duration> {
+        self.devices.get(name).map(Device::get_last_set_ago)
+    }
+
+    /// The raw brightness last read from or written to each device, sorted by device name.
+    /// In dry-run mode (see [`CalibrightBuilder::with_dry_run`]) these are the values that
+    /// would have been written.
+    pub fn cached_raw_brightness(&self) -> Vec<(&OsStr, u32)> {
+        let mut raw_brightnesses: Vec<(&OsStr, u32)> = self
+            .devices
+            .iter()
+            .map(|(name, device)| (name.as_os_str(), device.cached_raw_brightness()))
+            .collect();
+        raw_brightnesses.sort();
+
+        raw_brightnesses
+    }
+
+    /// Read every device once and return its `(raw, max_raw)` brightness, e.g. to display
+    /// `123/255`. Devices that can't be read are left out.
+    pub async fn raw_brightness_per_device(&mut self) -> Result<HashMap<OsString, (u32, u32)>> {
+        let raw_brightnesses =
+            join_all_accept_single_ok(self.devices.iter_mut().map(|(name, device)| async move {
+                let raw_brightness = device.peek_raw().await?;
+                device.update_raw_brightness(raw_brightness);
+                Ok((name.clone(), (raw_brightness, device.max_brightness())))
+            }))
+            .await?;
+
+        Ok(raw_brightnesses.into_iter().collect())
+    }
+
+    /// Like [`Calibright::raw_brightness_per_device`], but the cached brightness isn't updated,
+    /// so peeking at the hardware doesn't affect how changes are detected while watching.
+    pub async fn peek_raw_brightness(&self) -> Result<HashMap<OsString, (u32, u32)>> {
+        let raw_brightnesses =
+            join_all_accept_single_ok(self.devices.iter().map(|(name, device)| async move {
+                let raw_brightness = device.peek_raw().await?;
+                Ok((name.clone(), (raw_brightness, device.max_brightness())))
+            }))
+            .await?;
+
+        Ok(raw_brightnesses.into_iter().collect())
+    }
+
+    /// Read every device once and return a [`DeviceSnapshot`] for each, sorted by device name.
+    pub async fn snapshot(&mut self) -> Result<Vec<DeviceSnapshot>> {
+        let mut snapshots =
+            join_all_accept_single_ok(self.devices.iter_mut().map(|(_, device)| device.snapshot()))
+                .await?;
+        snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(snapshots)
+    }
+
+    /// Read every device again and list the devices whose brightness changed since `old` was
+    /// taken with [`Calibright::snapshot`], as `(name, old, new)` sorted by name, e.g. to detect
+    /// external changes by polling instead of watching.
+    ///
+    /// Changes are detected by raw value, devices that were added since `old` or can't be read now
+    /// aren't listed.
+    pub async fn diff(&mut self, old: &[DeviceSnapshot]) -> Result<Vec<(OsString, f64, f64)>> {
+        let new = self.snapshot().await?;
+
+        Ok(diff_snapshots(old, &new))
+    }
+
+    #[cfg(feature = "poll-watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "poll-watch")))]
+    /// Wait until the brightness of a device changes by reading every device each `interval`.
+    /// The changes since the previous call (or the first poll) are returned like
+    /// [`Calibright::diff`].
+    ///
+    /// Unlike watching with the `watch` feature this doesn't need notify/inotify, at the cost of
+    /// waking up and reading every device (a slow DDC transfer for ddcci devices) each `interval`,
+    /// and of noticing changes up to `interval` late. Devices aren't added or removed.
+    pub async fn poll_next(&mut self, interval: Duration) -> Result<Vec<(OsString, f64, f64)>> {
+        let mut old = match self.poll_snapshot.take() {
+            Some(old) => old,
+            None => self.snapshot().await?,
+        };
+        loop {
+            tokio::time::sleep(interval).await;
+            let new = self.snapshot().await?;
+            let changes = diff_snapshots(&old, &new);
+            old = new;
+            if !changes.is_empty() {
+                self.poll_snapshot = Some(old);
+                return Ok(changes);
+            }
+        }
+    }
+
+    /// Get the average screen brightness based on the calibration settings, weighted by each
+    /// device's `weight`. Brightness is in range 0.0 to 1.0 (inclusive).
+    ///
+    /// See [`CalibrightBuilder::with_get_aggregation`] to report e.g. the brightest device instead,
+    /// and [`CalibrightBuilder::with_display_calibration`] to remap it for display.
+    ///
+    /// With [`CalibrightBuilder::with_smoothing_factor`], this is the smoothed brightness.
+    ///
+    /// The other getters ([`Calibright::get_brightness_detailed`],
+    /// [`Calibright::get_brightness_with_deadline`] and [`Calibright::get_brightness_within`])
+    /// report the brightness the same way, and their readings are smoothed together with those of
+    /// this method. [`Calibright::get_brightness_cached`] applies the display transform too, but
+    /// isn't smoothed as it doesn't read the devices.
     pub async fn get_brightness(&mut self) -> Result<f64> {
+        let brightness = self.combined_brightness().await?;
+        self.report_brightness(brightness)
+    }
+
+    /// Apply the display transform and smoothing to a combined brightness that was just read,
+    /// like all getters that read the devices report it.
+    fn report_brightness(&mut self, brightness: f64) -> Result<f64> {
+        let brightness = self.display_brightness(brightness)?;
+        let smoothed = match self.smoothed_brightness {
+            Some(smoothed) => smoothed + self.smoothing_factor * (brightness - smoothed),
+            None => brightness,
+        };
+        self.smoothed_brightness = Some(smoothed);
+        Ok(smoothed)
+    }
+
+    /// The combined brightness of the devices, before the display transform. Brightness that is
+    /// written back has to be based on this.
+    async fn combined_brightness(&mut self) -> Result<f64> {
+        // The common single backlight case doesn't need to join futures
+        if let Some(device) = self.single_device() {
+            let brightness = device.get_weighted_brightness().await?;
+            return self.get_aggregation.aggregate(&[brightness]);
+        }
+
+        let uncounted = self.uncounted_devices();
+        let brightnesses = join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .filter(|(name, _)| !uncounted.contains(*name))
+                .map(|(_, device)| device.get_weighted_brightness()),
+        )
+        .await?;
+
+        self.get_aggregation.aggregate(&brightnesses)
+    }
+
+    /// Combine per-device brightnesses (e.g. the `percent` of each [`DeviceSnapshot`]) into the
+    /// value [`Calibright::get_brightness`] would report for them, using the configured
+    /// aggregation, `weight`s, device groups and display transform.
+    ///
+    /// Devices that aren't matched by this instance are weighted `1.0`.
+    pub fn aggregate<'a>(
+        &self,
+        brightnesses: impl IntoIterator<Item = (&'a OsStr, f64)>,
+    ) -> Result<f64> {
+        let uncounted = self.uncounted_devices();
+        let brightnesses: Vec<(f64, f64)> = brightnesses
+            .into_iter()
+            .filter(|(name, _)| !uncounted.contains(*name))
+            .map(|(name, brightness)| {
+                let weight = self
+                    .devices
+                    .get(name)
+                    .map_or(1.0, |device| device.config().weight);
+                (brightness, weight)
+            })
+            .collect();
+        let brightness = self.get_aggregation.aggregate(&brightnesses)?;
+        self.display_brightness(brightness)
+    }
+
+    /// Apply the display transform (see [`CalibrightBuilder::with_display_calibration`]) to a
+    /// combined brightness.
+    fn display_brightness(&self, brightness: f64) -> Result<f64> {
+        scale_to_clamped_relative(
+            brightness.powf(self.display_root_scaling.recip()),
+            self.display_calibration[0],
+            self.display_calibration[1],
+        )
+    }
+
+    /// Like [`Calibright::get_brightness`], but only of the primary device, e.g. the laptop panel
+    /// when there are also virtual devices that shouldn't affect the reported brightness.
+    ///
+    /// The primary device is the first one of [`Calibright::matched_device_names`], i.e. the one
+    /// whose name sorts first, so it is the same on every run. Narrow down the device regex if
+    /// that isn't the main screen.
+    pub async fn primary_brightness(&mut self) -> Result<f64> {
+        let device = self
+            .devices
+            .iter_mut()
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, device)| device)
+            .ok_or(CalibrightError::NoDevices)?;
+        let brightness = device.get_brightness().await?;
+        self.display_brightness(brightness)
+    }
+
+    /// The calibrated brightness of the device named `device` as a fraction of that of the
+    /// device named `reference`, e.g. to match monitors by eye. Both devices are read.
+    ///
+    /// Returns an error if either device doesn't exist or the reference is at `0.0`.
+    pub async fn relative_brightness(&mut self, device: &OsStr, reference: &OsStr) -> Result<f64> {
+        let reference_brightness = self.named_device(reference)?.get_brightness().await?;
+        if reference_brightness == 0.0 {
+            return Err(CalibrightError::Other(format!(
+                "Reference device {} is at 0.0, the relative brightness is undefined",
+                reference.to_string_lossy()
+            )));
+        }
+
+        Ok(self.named_device(device)?.get_brightness().await? / reference_brightness)
+    }
+
+    fn named_device(&mut self, name: &OsStr) -> Result<&mut Device> {
+        self.devices.get_mut(name).ok_or_else(|| {
+            CalibrightError::Other(format!("No device named {}", name.to_string_lossy()))
+        })
+    }
+
+    /// Wait until the brightness of every device is within `tolerance` of `target` (both in
+    /// range 0.0 to 1.0), e.g. for DDC/CI monitors that take a while to settle after a write.
+    ///
+    /// The devices are read repeatedly, devices that can't be read count as not settled yet.
+    /// Returns [`CalibrightError::Timeout`] if they haven't settled within `timeout`.
+    pub async fn wait_until_brightness(
+        &mut self,
+        target: f64,
+        tolerance: f64,
+        timeout: Duration,
+    ) -> Result<()> {
+        if !BRIGHTNESS_RANGE.contains(&target) {
+            return Err(CalibrightError::InvalidBrightness(target));
+        }
+        if self.devices.is_empty() {
+            return Err(CalibrightError::NoDevices);
+        }
+        let start = Instant::now();
+        loop {
+            let brightnesses = join_all(
+                self.devices
+                    .values_mut()
+                    .map(|device| device.get_brightness()),
+            )
+            .await;
+            let settled = brightnesses.iter().all(|brightness| {
+                brightness
+                    .as_ref()
+                    .is_ok_and(|brightness| (brightness - target).abs() <= tolerance)
+            });
+            if settled {
+                debug!("reached {target} after {:?}", start.elapsed());
+                return Ok(());
+            }
+
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return Err(CalibrightError::Timeout(timeout));
+            }
+            tokio::time::sleep(remaining.min(WAIT_POLL_INTERVAL)).await;
+        }
+    }
+
+    /// Like [`Calibright::get_brightness`], but as a percentage from 0 to 100, like the CLI uses.
+    ///
+    /// The percentage is rounded to the nearest integer with halves rounded up, e.g. `0.005` is
+    /// `1` and `0.004` is `0`, and `0.995` is `100`.
+    pub async fn get_brightness_percent(&mut self) -> Result<u8> {
+        // Float error (e.g. `0.00499999`) doesn't decide the rounding at a half
+        let percent = (self.get_brightness().await? * 100.0 + BRIGHTNESS_SNAP_EPSILON).round();
+        Ok(percent.clamp(0.0, 100.0) as u8)
+    }
+
+    /// Like [`Calibright::set_brightness`], but as a percentage from 0 to 100, like the CLI uses.
+    /// Values above 100 are handled according to the [`OverflowPolicy`].
+    pub async fn set_brightness_percent(&mut self, percent: u8) -> Result<()> {
+        self.set_brightness(f64::from(percent) / 100.0).await
+    }
+
+    /// Like [`Calibright::get_brightness`], but also reports how many devices could be read, e.g.
+    /// to warn that only 2 of 3 monitors are reporting.
+    pub async fn get_brightness_detailed(&mut self) -> Result<AveragedBrightness> {
+        let uncounted = self.uncounted_devices();
+        let total = self.devices.len() - uncounted.len();
+        let brightnesses = join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .filter(|(name, _)| !uncounted.contains(*name))
+                .map(|(_, device)| device.get_weighted_brightness()),
+        )
+        .await?;
+
+        let brightness = self.get_aggregation.aggregate(&brightnesses)?;
+
+        Ok(AveragedBrightness {
+            value: self.report_brightness(brightness)?,
+            contributing: brightnesses.len(),
+            total,
+        })
+    }
+
+    /// Like [`Calibright::get_brightness`], but gives each device at most `deadline` to be read
+    /// and reports which devices failed, e.g. to show that `ddcci7` timed out after 2s instead of
+    /// silently leaving it out.
+    ///
+    /// The devices are read concurrently. Returns the error of a device if none could be read.
+    pub async fn get_brightness_with_deadline(
+        &mut self,
+        deadline: Duration,
+    ) -> Result<PartialBrightness> {
+        let uncounted = self.uncounted_devices();
+        let results = join_all(
+            self.devices
+                .iter_mut()
+                .filter(|(name, _)| !uncounted.contains(*name))
+                .map(|(name, device)| async move {
+                    tokio::time::timeout(deadline, device.get_weighted_brightness())
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(CalibrightError::TimedOut {
+                                device: name.clone(),
+                                waited: deadline,
+                            })
+                        })
+                }),
+        )
+        .await;
+
+        let mut brightnesses = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(brightness) => brightnesses.push(brightness),
+                Err(e) => {
+                    debug!("{e}");
+                    errors.push(e);
+                }
+            }
+        }
+        if brightnesses.is_empty() {
+            return Err(errors.pop().unwrap_or(CalibrightError::NoDevices));
+        }
+        let brightness = self.get_aggregation.aggregate(&brightnesses)?;
+
+        Ok(PartialBrightness {
+            value: self.report_brightness(brightness)?,
+            errors,
+        })
+    }
+
+    /// Like [`Calibright::get_brightness`], but returns within `deadline`, e.g. for a status bar
+    /// with a strict frame budget. The devices are read concurrently and those that haven't been
+    /// read by then are left out.
+    ///
+    /// Returns [`CalibrightError::TimedOut`] if no device could be read in time. Use
+    /// [`Calibright::get_brightness_with_deadline`] to also find out which devices were left out.
+    pub async fn get_brightness_within(&mut self, deadline: Duration) -> Result<f64> {
+        Ok(self.get_brightness_with_deadline(deadline).await?.value)
+    }
+
+    /// Like [`Calibright::get_brightness`], but based on the cached brightness of each device
+    /// instead of reading the devices, so it returns immediately. The display transform is
+    /// applied, but not the smoothing.
+    ///
+    /// The cache is updated by every read and write, and by the background refresh and
+    /// coalesced writes if enabled (see [`CalibrightBuilder::with_background_refresh`] and
+    /// [`CalibrightBuilder::with_coalesced_writes`]).
+    pub fn get_brightness_cached(&self) -> Result<f64> {
+        let uncounted = self.uncounted_devices();
+        let brightnesses = self
+            .devices
+            .iter()
+            .filter(|(name, _)| !uncounted.contains(*name))
+            .map(|(_, device)| Ok((device.cached_brightness()?, device.config().weight)))
+            .collect::<Result<Vec<_>>>()?;
+        let brightness = self.get_aggregation.aggregate(&brightnesses)?;
+
+        self.display_brightness(brightness)
+    }
+
+    /// Devices that are left out when reading the brightness, because another member of their
+    /// group (see [`CalibrightBuilder::with_device_group`]) is counted instead.
+    fn uncounted_devices(&self) -> HashSet<OsString> {
+        let mut uncounted = HashSet::new();
+        for group in &self.device_groups {
+            let mut present = group.iter().filter(|name| self.devices.contains_key(*name));
+            // The first member found is counted
+            present.next();
+            uncounted.extend(present.cloned());
+        }
+        uncounted
+    }
+
+    /// The largest change in brightness of one raw step of any device at its cached brightness,
+    /// e.g. to snap a slider to levels that can actually be set.
+    /// See [`Calibright::get_brightness_cached`] for how the cache is updated.
+    pub fn coarsest_step(&self) -> Result<f64> {
+        let steps = self
+            .devices
+            .values()
+            .map(Device::min_step_percent)
+            .collect::<Result<Vec<_>>>()?;
+
+        steps
+            .into_iter()
+            .reduce(f64::max)
+            .ok_or(CalibrightError::NoDevices)
+    }
+
+    /// Get the lowest and highest device brightness based on the calibration settings, as
+    /// `(min, max)`. Brightness is in range 0.0 to 1.0 (inclusive).
+    pub async fn brightness_bounds(&mut self) -> Result<(f64, f64)> {
         let brightnesses = join_all_accept_single_ok(
             self.devices
                 .iter_mut()
@@ -266,12 +2025,122 @@ impl Calibright {
         )
         .await?;
 
-        Ok(brightnesses.iter().sum::<f64>() / (brightnesses.len() as f64))
+        Ok(brightnesses.iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(min, max), &brightness| (min.min(brightness), max.max(brightness)),
+        ))
+    }
+
+    /// Get the average brightness of the devices of a single subsystem based on the calibration
+    /// settings, weighted by each device's `weight`. Brightness is in range 0.0 to 1.0 (inclusive).
+    /// Devices are combined the same way as by [`Calibright::get_brightness`].
+    pub async fn get_subsystem_brightness(&mut self, subsystem: Subsystem) -> Result<f64> {
+        let uncounted = self.uncounted_devices();
+        let brightnesses = join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .filter(|(name, device)| {
+                    device.subsystem == subsystem && !uncounted.contains(*name)
+                })
+                .map(|(_, device)| device.get_weighted_brightness()),
+        )
+        .await?;
+
+        self.get_aggregation.aggregate(&brightnesses)
+    }
+
+    /// The brightness [`Calibright::get_brightness`] would report after setting `brightness`
+    /// with [`Calibright::set_brightness`] (ignoring relative mode and
+    /// [`SetAggregation::Preserve`]), which can differ from `brightness` due to rounding to the
+    /// raw steps of the devices. Nothing is written.
+    pub fn effective_brightness(&self, brightness: f64) -> Result<f64> {
+        let uncounted = self.uncounted_devices();
+        let brightnesses = self
+            .devices
+            .iter()
+            .filter(|(name, _)| !uncounted.contains(*name))
+            .map(|(_, device)| {
+                Ok((
+                    device.effective_brightness(brightness)?,
+                    device.config().weight,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.get_aggregation.aggregate(&brightnesses)
+    }
+
+    /// The hardware brightness of each device, as a ratio of the raw value to the max raw value
+    /// (0.0..=1.0), that [`Calibright::set_brightness`] with `brightness` would write (ignoring
+    /// relative mode and [`SetAggregation::Preserve`]). Nothing is written.
+    pub fn hardware_brightness_per_device(
+        &self,
+        brightness: f64,
+    ) -> Result<HashMap<OsString, f64>> {
+        self.devices
+            .iter()
+            .map(|(name, device)| Ok((name.clone(), device.hardware_brightness(brightness)?)))
+            .collect()
     }
 
     /// Set the screen brightness based on the calibration settings.
     /// Brightness is in range 0.0 to 1.0 (inclusive).
+    ///
+    /// If relative mode is enabled (see [`CalibrightBuilder::with_relative_mode`]), `brightness`
+    /// is instead the amount (-1.0 to 1.0) to adjust each device by. Otherwise see
+    /// [`CalibrightBuilder::with_set_aggregation`].
+    ///
+    /// With [`CalibrightBuilder::with_coalesced_writes`], this returns before the brightness is
+    /// written.
+    ///
+    /// With [`CalibrightBuilder::with_state_file`], the raw brightness of each device is recorded
+    /// afterwards. Failing to write the state file is only logged.
     pub async fn set_brightness(&mut self, brightness: f64) -> Result<()> {
+        self.apply_brightness(brightness).await?;
+        if let Some(state_file) = &self.state_file {
+            let state = State {
+                devices: self
+                    .devices
+                    .iter()
+                    .map(|(name, device)| {
+                        (
+                            name.to_string_lossy().into_owned(),
+                            device.cached_raw_brightness(),
+                        )
+                    })
+                    .collect(),
+            };
+            if let Err(e) = state.save(state_file).await {
+                debug!("Failed to write state file {}: {e}", state_file.display());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the brightness without recording it in the state file.
+    async fn apply_brightness(&mut self, brightness: f64) -> Result<()> {
+        if self.relative_mode {
+            return self.adjust_brightness(brightness).await;
+        }
+        if self.set_aggregation == SetAggregation::Preserve {
+            let current = self.combined_brightness().await?;
+            return self.adjust_brightness(brightness - current).await;
+        }
+        if let Some(coalesced_writer) = &self.coalesced_writer {
+            // Report out of range values here, as errors of the write are only logged
+            let brightness = self.device_options.overflow_policy.apply(brightness)?;
+            for device in self.devices.values_mut() {
+                device.mark_pending_write();
+            }
+            coalesced_writer.set(brightness);
+            return Ok(());
+        }
+
+        if let Some(device) = self.single_device() {
+            return device.set_brightness(brightness).await;
+        }
+
         join_all_accept_single_ok(
             self.devices
                 .iter_mut()
@@ -281,4 +2150,1167 @@ impl Calibright {
 
         Ok(())
     }
+
+    /// Like [`Calibright::set_brightness`] with `value` limited to `ceiling`, e.g. a maximum
+    /// derived from an ambient light sensor, without changing the config. Both are clamped to
+    /// 0.0 to 1.0.
+    ///
+    /// The ceiling is in the same calibrated range as `value`, so it is applied before each
+    /// device's calibration: with a ceiling of `0.5`, every device is at most at the raw level
+    /// that `set_brightness(0.5)` would write to it, not half of its hardware range.
+    pub async fn set_brightness_with_ceiling(&mut self, value: f64, ceiling: f64) -> Result<()> {
+        for brightness in [value, ceiling] {
+            if brightness.is_nan() {
+                return Err(CalibrightError::InvalidBrightness(brightness));
+            }
+        }
+        self.set_brightness(value.clamp(0.0, 1.0).min(ceiling.clamp(0.0, 1.0)))
+            .await
+    }
+
+    /// Set the raw brightness of each device to the one recorded in the state file, see
+    /// [`CalibrightBuilder::with_state_file`]. Devices without a recorded brightness are left
+    /// alone.
+    ///
+    /// Nothing is restored if no state file is configured, or if it is missing or corrupt.
+    pub async fn restore_from_state(&mut self) -> Result<()> {
+        let Some(state_file) = &self.state_file else {
+            return Ok(());
+        };
+        let Some(state) = State::load(state_file).await else {
+            return Ok(());
+        };
+        let restores: Vec<_> = self
+            .devices
+            .iter_mut()
+            .filter_map(|(name, device)| {
+                // The max brightness may have changed, e.g. with another monitor on the same bus
+                let raw_brightness =
+                    (*state.devices.get(&*name.to_string_lossy())?).min(device.max_brightness());
+                debug!("Restoring {name:?} to {raw_brightness}");
+                Some(device.set_brightness_raw(raw_brightness))
+            })
+            .collect();
+        if !restores.is_empty() {
+            join_all_accept_single_ok(restores).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Like [`Calibright::set_brightness`], but gives up after `deadline` with
+    /// [`CalibrightError::Timeout`]. Devices that weren't written by then keep their brightness.
+    pub async fn set_brightness_within(
+        &mut self,
+        brightness: f64,
+        deadline: Duration,
+    ) -> Result<()> {
+        tokio::time::timeout(deadline, self.set_brightness(brightness))
+            .await
+            .map_err(|_| CalibrightError::Timeout(deadline))?
+    }
+
+    /// Like [`Calibright::set_brightness`], but returns the brightness that was actually reached
+    /// after rounding to the raw steps of the devices (reported like
+    /// [`Calibright::get_brightness_cached`]), e.g. to keep a slider in sync with coarse DDC/CI
+    /// monitors.
+    pub async fn try_set_brightness(&mut self, brightness: f64) -> Result<f64> {
+        self.set_brightness(brightness).await?;
+        self.get_brightness_cached()
+    }
+
+    /// Like [`Calibright::set_brightness`] (ignoring relative mode and
+    /// [`SetAggregation::Preserve`]), but only for the devices whose name or connector name
+    /// matches `pattern`, e.g. to dim only the external monitors.
+    ///
+    /// Returns [`CalibrightError::NoDevices`] if `pattern` matches none of the devices.
+    pub async fn set_brightness_matching(
+        &mut self,
+        pattern: &Regex,
+        brightness: f64,
+    ) -> Result<()> {
+        let devices: Vec<&mut Device> = self
+            .devices
+            .iter_mut()
+            .filter(|(name, device)| {
+                pattern.is_match(&name.to_string_lossy())
+                    || device
+                        .connector
+                        .as_deref()
+                        .is_some_and(|connector| pattern.is_match(connector))
+            })
+            .map(|(_, device)| device)
+            .collect();
+        if devices.is_empty() {
+            return Err(CalibrightError::NoDevices);
+        }
+
+        join_all_accept_single_ok(
+            devices
+                .into_iter()
+                .map(|device| device.set_brightness(brightness)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// The device, if there is exactly one.
+    fn single_device(&mut self) -> Option<&mut Device> {
+        if self.devices.len() == 1 {
+            self.devices.values_mut().next()
+        } else {
+            None
+        }
+    }
+
+    /// Like [`Calibright::set_brightness`] (ignoring relative mode and
+    /// [`SetAggregation::Preserve`]), but either all devices are set or none are.
+    ///
+    /// If setting any device fails, the devices that were already set are restored to the
+    /// brightness they had before, and the first error is returned.
+    pub async fn set_brightness_atomic(&mut self, brightness: f64) -> Result<()> {
+        if self.devices.is_empty() {
+            return Err(CalibrightError::NoDevices);
+        }
+        let mut devices: Vec<&mut Device> = self.devices.values_mut().collect();
+        let originals = join_all(devices.iter().map(|device| device.peek_raw()))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<u32>>>()?;
+
+        let results = join_all(
+            devices
+                .iter_mut()
+                .map(|device| device.set_brightness(brightness)),
+        )
+        .await;
+        let mut first_error = None;
+        let mut succeeded = Vec::new();
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(()) => succeeded.push(index),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        let Some(error) = first_error else {
+            return Ok(());
+        };
+
+        let restored = join_all(
+            devices
+                .iter_mut()
+                .enumerate()
+                .filter(|(index, _)| succeeded.contains(index))
+                .map(|(index, device)| device.set_brightness_raw(originals[index])),
+        )
+        .await;
+        for result in restored {
+            if let Err(e) = result {
+                debug!("failed to restore brightness: {e}");
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Adjust the brightness of each device by `delta` (-1.0 to 1.0) relative to its own current
+    /// brightness, based on the calibration settings.
+    ///
+    /// Unlike setting the average brightness plus `delta`, this preserves the differences
+    /// between devices.
+    pub async fn adjust_brightness(&mut self, delta: f64) -> Result<()> {
+        join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .map(|(_, device)| device.adjust_brightness(delta)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Increase the brightness by `step`, wrapping around to `min` once `max` was reached, e.g. to
+    /// cycle through brightness levels with a single key. Brightness is in range 0.0 to 1.0
+    /// (inclusive) based on the calibration settings.
+    ///
+    /// Unlike [`Calibright::adjust_brightness`], which stops at the upper end, this sets `max`
+    /// first and wraps on the next call. The brightness is considered to be at `max` when it is
+    /// within half a step of it, to allow for rounding to raw values.
+    pub async fn cycle_brightness(&mut self, step: f64, min: f64, max: f64) -> Result<()> {
+        if min > max {
+            return Err(CalibrightError::InvalidScaleParameters {
+                low: min,
+                high: max,
+            });
+        }
+        if step.is_nan() || step <= 0.0 {
+            return Err(CalibrightError::Other(format!(
+                "Cycle step must be greater than 0.0, got {step}"
+            )));
+        }
+
+        let brightness = self.combined_brightness().await?;
+        let next = if brightness >= max - step / 2.0 {
+            min
+        } else {
+            (brightness + step).min(max)
+        };
+        self.set_brightness(next).await
+    }
+
+    /// Get the average luminance in nits of the devices with a configured `max_nits`, weighted by
+    /// each device's `weight`. Devices without `max_nits` are skipped.
+    pub async fn get_brightness_nits(&mut self) -> Result<f64> {
+        self.check_max_nits_configured()?;
+        let uncounted = self.uncounted_devices();
+        let nits = join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .filter(|(name, device)| {
+                    device.config().max_nits.is_some() && !uncounted.contains(*name)
+                })
+                .map(|(_, device)| async {
+                    let weight = device.config().weight;
+                    Ok((device.get_brightness_nits().await?, weight))
+                }),
+        )
+        .await?;
+
+        weighted_mean(&nits)
+    }
+
+    /// Set the luminance in nits of the devices with a configured `max_nits`. Devices without
+    /// `max_nits` are left unchanged, and an error is returned if no device has it configured.
+    ///
+    /// The luminance is assumed to be linear in the raw brightness, the calibration settings
+    /// don't apply.
+    pub async fn set_brightness_nits(&mut self, nits: f64) -> Result<()> {
+        self.check_max_nits_configured()?;
+        join_all_accept_single_ok(
+            self.devices
+                .values_mut()
+                .filter(|device| device.config().max_nits.is_some())
+                .map(|device| device.set_brightness_nits(nits)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    fn check_max_nits_configured(&self) -> Result<()> {
+        if self
+            .devices
+            .values()
+            .any(|device| device.config().max_nits.is_some())
+        {
+            Ok(())
+        } else {
+            Err(CalibrightError::Other(
+                "None of the devices have max_nits configured".into(),
+            ))
+        }
+    }
+
+    /// Set the screen brightness ignoring the calibration settings.
+    /// Brightness is in range 0.0 to 1.0 (inclusive) of the full hardware range of each device.
+    ///
+    /// This differs from [`Calibright::set_brightness`] in that the configured `calibration`
+    /// window is skipped, so `1.0` always sets each device to its hardware max.
+    /// `root_scaling` is still applied.
+    pub async fn set_brightness_uncalibrated(&mut self, brightness: f64) -> Result<()> {
+        join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .map(|(_, device)| device.set_brightness_uncalibrated(brightness)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set each device to the brightness of the `[profiles.<name>]` config section with the given
+    /// name, e.g. to switch between a "day" and a "night" profile. Brightness is set based on the
+    /// calibration settings.
+    ///
+    /// Devices that aren't listed use the `global` entry of the profile, or are left unchanged if
+    /// there is none.
+    pub async fn apply_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self
+            .config
+            .profile(name)
+            .ok_or_else(|| CalibrightError::Other(format!("No profile named {name:?}")))?;
+
+        join_all_accept_single_ok(self.devices.iter_mut().filter_map(|(device_name, device)| {
+            let brightness = *profile
+                .get(device_name.to_string_lossy().as_ref())
+                .or_else(|| profile.get("global"))?;
+            Some(device.set_brightness(brightness))
+        }))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Switch every device to the config of the `[[schedule]]` window containing `time_of_day`
+    /// (the time since local midnight), or back to its regular config outside of all windows,
+    /// e.g. for a dimmer `calibration` in the evening.
+    ///
+    /// There is no timer, call this whenever the schedule should be checked, e.g. every minute.
+    /// The brightness isn't changed until the next time it is set.
+    pub fn apply_scheduled_config(&mut self, time_of_day: Duration) {
+        let minute = (time_of_day.as_secs() / 60 % u64::from(MINUTES_PER_DAY)) as u32;
+        for (name, device) in &mut self.devices {
+            let config = self.config.get_scheduled_device_config(
+                &name.to_string_lossy().to_string(),
+                device.connector.as_deref(),
+                minute,
+            );
+            device.set_config(config);
+        }
+    }
+
+    /// Set every device to a fraction (0.0..=1.0) of its raw hardware range, e.g. `0.5` sets a
+    /// device with a `max_brightness` of `255` to `128`.
+    ///
+    /// This differs from [`Calibright::set_brightness_uncalibrated`] in that `root_scaling`,
+    /// `gamma` and `offset` aren't applied either, and from setting raw values in that it works
+    /// the same for devices with different `max_brightness`. The raw value of each device is
+    /// clamped to `1..=max_brightness`, so `0.0` doesn't turn off the backlight.
+    pub async fn set_hardware_percent(&mut self, percent: f64) -> Result<()> {
+        join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .map(|(_, device)| device.set_hardware_percent(percent)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set every device to its hardware max.
+    ///
+    /// Unlike `set_brightness(1.0)`, which is limited to the upper bound of the `calibration`
+    /// window, this bypasses all of the calibration settings. Only `max_brightness_cap` is still
+    /// enforced.
+    pub async fn maximize(&mut self) -> Result<()> {
+        join_all_accept_single_ok(self.devices.iter_mut().map(|(_, device)| device.maximize()))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Set every device to the lowest brightness that doesn't turn off the backlight, or to off
+    /// with [`CalibrightBuilder::with_allow_zero`].
+    ///
+    /// Unlike `set_brightness(0.0)`, which is limited to the lower bound of the `calibration`
+    /// window, this bypasses all of the calibration settings.
+    pub async fn minimize(&mut self) -> Result<()> {
+        join_all_accept_single_ok(self.devices.iter_mut().map(|(_, device)| device.minimize()))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Move the raw brightness of each device by `steps` (negative to decrease), e.g. for exactly
+    /// one level on a coarse DDC/CI monitor where percentages either do nothing or jump too far.
+    /// The calibration settings don't apply, the raw value is clamped to `1..=max_brightness`.
+    pub async fn adjust_raw(&mut self, steps: i32) -> Result<()> {
+        join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .map(|(_, device)| device.adjust_raw(steps)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Increase the brightness of each device by one perceptually even step.
+    /// See [`CalibrightBuilder::with_step_count`].
+    pub async fn step_up(&mut self) -> Result<()> {
+        join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .map(|(_, device)| device.step_brightness(1)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Decrease the brightness of each device by one perceptually even step.
+    /// See [`CalibrightBuilder::with_step_count`].
+    pub async fn step_down(&mut self) -> Result<()> {
+        join_all_accept_single_ok(
+            self.devices
+                .iter_mut()
+                .map(|(_, device)| device.step_brightness(-1)),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::fs::tests::*;
+
+    const NAME: &str = "calibright_test";
+
+    /// Two backlights `a` at 20% and `b` at 60%.
+    fn two_backlights() -> Arc<MemoryFs> {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, "a", 20, 100);
+        insert_backlight(&fs, "b", 60, 100);
+        fs
+    }
+
+    /// The raw brightness written to each of the backlights.
+    fn written(fs: &MemoryFs, names: &[&str]) -> Vec<u32> {
+        names
+            .iter()
+            .map(|name| read_number(fs, backlight_file(name, FILE_BRIGHTNESS_WRITE)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn absolute_mode_sets_all_devices_to_the_same_level() {
+        let fs = two_backlights();
+        let mut calibright = calibright(&fs).await;
+        calibright.set_brightness(0.5).await.unwrap();
+        assert_eq!(written(&fs, &["a", "b"]), [50, 50]);
+    }
+
+    #[tokio::test]
+    async fn relative_mode_keeps_the_differences_between_devices() {
+        let fs = two_backlights();
+        let mut calibright = builder(&fs).with_relative_mode(true).build().await.unwrap();
+        calibright.set_brightness(0.1).await.unwrap();
+        assert_eq!(written(&fs, &["a", "b"]), [30, 70]);
+    }
+
+    #[tokio::test]
+    async fn missing_subsystem_directories_are_no_devices() {
+        // No `/sys/class/backlight` at all, like on a desktop without a backlight
+        let fs = Arc::new(MemoryFs::new());
+        let res = builder(&fs).build().await;
+        assert!(matches!(res, Err(CalibrightError::NoDevices)));
+
+        // Only the missing subsystems are skipped
+        insert_backlight(&fs, NAME, 50, 100);
+        let calibright = builder(&fs)
+            .with_subsystems(&[Subsystem::Leds, Subsystem::Backlight])
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(calibright.matched_device_names(), [NAME]);
+    }
+
+    #[tokio::test]
+    async fn alternate_max_files_are_found() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.insert(backlight_file("a", "max_level"), "200");
+        fs.insert(backlight_file("a", FILE_BRIGHTNESS), "100");
+        fs.insert(backlight_file("a", FILE_BRIGHTNESS_WRITE), "100");
+        fs.insert(backlight_file("b", "range"), "10");
+        fs.insert(backlight_file("b", FILE_BRIGHTNESS), "2");
+        fs.insert(backlight_file("b", FILE_BRIGHTNESS_WRITE), "2");
+
+        let mut calibright = builder(&fs).build().await.unwrap();
+        assert_eq!(calibright.matched_device_names(), ["a"]);
+        assert_eq!(calibright.get_brightness().await.unwrap(), 0.5);
+
+        let mut calibright = builder(&fs)
+            .with_config(config("[b]\nmax_file = \"range\""))
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(calibright.matched_device_names(), ["a", "b"]);
+        calibright.set_brightness(0.5).await.unwrap();
+        assert_eq!(written(&fs, &["a", "b"]), [100, 5]);
+    }
+
+    #[tokio::test]
+    async fn adjust_raw_moves_exactly_one_level() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 4, 10);
+        let read_file = backlight_file(NAME, FILE_BRIGHTNESS);
+        let mut calibright = calibright(&fs).await;
+
+        calibright.adjust_raw(1).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [5]);
+        fs.insert(&read_file, "5");
+        calibright.adjust_raw(-1).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [4]);
+
+        // Clamped to 1..=max_brightness
+        fs.insert(&read_file, "10");
+        calibright.adjust_raw(1).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [10]);
+        fs.insert(&read_file, "1");
+        calibright.adjust_raw(-1).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [1]);
+    }
+
+    #[tokio::test]
+    async fn brightness_filenames_replace_the_sysfs_defaults() {
+        let fs = Arc::new(MemoryFs::new());
+        fs.insert(backlight_file(NAME, "level_max"), "200");
+        fs.insert(backlight_file(NAME, "level_now"), "50");
+        fs.insert(backlight_file(NAME, "level_set"), "50");
+        let mut calibright = builder(&fs)
+            .with_brightness_filenames("level_now", "level_set", "level_max")
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(calibright.matched_device_names(), [NAME]);
+        assert_eq!(calibright.get_brightness().await.unwrap(), 0.25);
+        calibright.set_brightness(0.5).await.unwrap();
+        assert_eq!(read_number(&fs, backlight_file(NAME, "level_set")), 100);
+        assert!(fs
+            .get(backlight_file(NAME, FILE_BRIGHTNESS_WRITE))
+            .is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_until_brightness_waits_for_the_devices_to_settle() {
+        let fs = two_backlights();
+        let mut calibright = calibright(&fs).await;
+        let timeout = Duration::from_secs(5);
+        let settle = tokio::spawn({
+            let fs = fs.clone();
+            async move {
+                tokio::time::sleep(WAIT_POLL_INTERVAL * 2).await;
+                fs.insert(backlight_file("a", FILE_BRIGHTNESS), "49");
+                fs.insert(backlight_file("b", FILE_BRIGHTNESS), "51");
+            }
+        });
+
+        let start = Instant::now();
+        calibright
+            .wait_until_brightness(0.5, 0.02, timeout)
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= WAIT_POLL_INTERVAL * 2);
+        assert!(start.elapsed() < timeout);
+        settle.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_until_brightness_times_out() {
+        let fs = two_backlights();
+        let mut calibright = calibright(&fs).await;
+        let timeout = WAIT_POLL_INTERVAL * 3;
+
+        let start = Instant::now();
+        let res = calibright.wait_until_brightness(0.5, 0.02, timeout).await;
+        assert!(matches!(res, Err(CalibrightError::Timeout(t)) if t == timeout));
+        assert!(start.elapsed() >= timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ddc_base_delay_overrides_the_config() {
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 50, 100);
+        let toml = "[global]\nddcci_max_tries_write_read = 3\nddcci_read_base_ms = 1";
+        let mut calibright = builder(&fs)
+            .with_config(config(toml))
+            .with_ddc_base_delay(Duration::from_millis(100))
+            .build()
+            .await
+            .unwrap();
+
+        // Two retries, each after the base delay of the builder rather than of the config
+        fs.fail_reads(backlight_file(NAME, FILE_BRIGHTNESS), 2);
+        let start = Instant::now();
+        assert_eq!(calibright.get_brightness().await.unwrap(), 0.5);
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+
+        for delay in [Duration::ZERO, Duration::from_millis(1001)] {
+            let res = builder(&fs).with_ddc_base_delay(delay).build().await;
+            assert!(res.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_defaults_are_rejected_with_a_config() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+
+        let res = builder(&fs)
+            .with_default_calibration([5.0, 200.0])
+            .build()
+            .await;
+        assert!(res.is_err());
+        let res = builder(&fs).with_default_root_scaling(0.0).build().await;
+        assert!(res.is_err());
+        let res = builder(&fs)
+            .with_default_calibration([5.0, 95.0])
+            .with_default_root_scaling(2.0)
+            .build()
+            .await;
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn display_transform_only_applies_to_get_brightness() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        let read_file = backlight_file(NAME, FILE_BRIGHTNESS);
+
+        let mut calibright = builder(&fs)
+            .with_display_calibration([20.0, 80.0])
+            .build()
+            .await
+            .unwrap();
+        calibright.set_brightness(0.25).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [25]);
+        fs.insert(&read_file, "25");
+        assert!((calibright.get_brightness().await.unwrap() - 0.35).abs() < 1e-9);
+
+        let mut calibright = builder(&fs)
+            .with_display_root_scaling(2.0)
+            .build()
+            .await
+            .unwrap();
+        calibright.set_brightness(0.25).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [25]);
+        assert!((calibright.get_brightness().await.unwrap() - 0.5).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn all_getters_report_like_get_brightness() {
+        let fs = two_backlights();
+        let mut calibright = builder(&fs)
+            .with_display_root_scaling(2.0)
+            .with_smoothing_factor(0.5)
+            .build()
+            .await
+            .unwrap();
+        let deadline = Duration::from_secs(1);
+        let displayed = 0.4_f64.sqrt();
+        assert!((calibright.get_brightness().await.unwrap() - displayed).abs() < 1e-9);
+        let detailed = calibright.get_brightness_detailed().await.unwrap();
+        assert!((detailed.value - displayed).abs() < 1e-9);
+        let partial = calibright
+            .get_brightness_with_deadline(deadline)
+            .await
+            .unwrap();
+        assert!((partial.value - displayed).abs() < 1e-9);
+        let within = calibright.get_brightness_within(deadline).await.unwrap();
+        assert!((within - displayed).abs() < 1e-9);
+        assert!((calibright.get_brightness_cached().unwrap() - displayed).abs() < 1e-9);
+
+        // The readings of all getters are smoothed together, the cached brightness isn't smoothed
+        fs.insert(backlight_file("a", FILE_BRIGHTNESS), "60");
+        let target = 0.6_f64.sqrt();
+        let detailed = calibright.get_brightness_detailed().await.unwrap();
+        assert!((detailed.value - (displayed + target) / 2.0).abs() < 1e-9);
+        let within = calibright.get_brightness_within(deadline).await.unwrap();
+        assert!((within - (displayed + 3.0 * target) / 4.0).abs() < 1e-9);
+        assert!((calibright.get_brightness_cached().unwrap() - target).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn denied_writes_report_permission_denied() {
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 50, 100);
+        let mut calibright = calibright(&fs).await;
+        calibright.set_brightness(0.3).await.unwrap();
+
+        // E.g. the device was rebound and lost the permissions granted by udev
+        let write_file = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        fs.deny_writes(&write_file);
+        let err = calibright.set_brightness(0.6).await.unwrap_err();
+        assert!(err.to_string().contains("udev rule"));
+        let CalibrightError::Device { name, source } = err else {
+            panic!("expected CalibrightError::Device");
+        };
+        assert_eq!(name, NAME);
+        let CalibrightError::PermissionDenied { path, .. } = *source else {
+            panic!("expected CalibrightError::PermissionDenied");
+        };
+        assert_eq!(path, write_file);
+        assert_eq!(read_number(&fs.fs, &write_file), 30);
+    }
+
+    /// A [`Calibright`] for the backlights of [`two_backlights`], where reading `b` takes a
+    /// second once it was found.
+    async fn fast_and_slow_backlights() -> (Arc<FlakyFs>, Calibright) {
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, "a", 20, 100);
+        insert_backlight(&fs.fs, "b", 60, 100);
+        let calibright = calibright(&fs).await;
+        fs.delay_reads(backlight_file("b", FILE_BRIGHTNESS), Duration::from_secs(1));
+        (fs, calibright)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn devices_missing_the_deadline_are_reported() {
+        let (_fs, mut calibright) = fast_and_slow_backlights().await;
+        let deadline = Duration::from_millis(50);
+
+        let partial = calibright
+            .get_brightness_with_deadline(deadline)
+            .await
+            .unwrap();
+        assert_eq!(partial.value, 0.2);
+        let [CalibrightError::TimedOut { device, waited }] = &partial.errors[..] else {
+            panic!("expected one timeout, got {:?}", partial.errors);
+        };
+        assert_eq!(device, "b");
+        assert_eq!(*waited, deadline);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn get_brightness_within_leaves_out_slow_devices() {
+        let (fs, mut calibright) = fast_and_slow_backlights().await;
+        let deadline = Duration::from_millis(50);
+
+        let start = Instant::now();
+        assert_eq!(
+            calibright.get_brightness_within(deadline).await.unwrap(),
+            0.2
+        );
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        fs.delay_reads(backlight_file("a", FILE_BRIGHTNESS), Duration::from_secs(1));
+        let res = calibright.get_brightness_within(deadline).await;
+        assert!(matches!(res, Err(CalibrightError::TimedOut { .. })));
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn smoothed_brightness_converges_to_the_readings() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 20, 100);
+        let mut calibright = builder(&fs)
+            .with_smoothing_factor(0.5)
+            .build()
+            .await
+            .unwrap();
+        assert!((calibright.get_brightness().await.unwrap() - 0.2).abs() < 1e-9);
+
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "100");
+        let mut previous = 0.2;
+        for expected in [0.6, 0.8, 0.9, 0.95, 0.975] {
+            let brightness = calibright.get_brightness().await.unwrap();
+            assert!((brightness - expected).abs() < 1e-9, "{brightness}");
+            assert!(brightness > previous);
+            previous = brightness;
+        }
+
+        // Writes aren't smoothed
+        calibright.set_brightness(0.3).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [30]);
+
+        let res = builder(&fs).with_smoothing_factor(0.0).build().await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn background_refresh_updates_the_cache() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        let refresh_interval = Duration::from_millis(10);
+        let calibright = builder(&fs)
+            .with_background_refresh(refresh_interval)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(calibright.get_brightness_cached().unwrap(), 0.5);
+
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "80");
+        // With the time paused, sleeping only moves the time on once the refresh task is idle
+        tokio::time::sleep(refresh_interval * 5).await;
+        assert_eq!(calibright.get_brightness_cached().unwrap(), 0.8);
+        // The reading is in the device owned by `calibright`, not just a copy
+        let device = &calibright.devices[OsStr::new(NAME)];
+        assert_eq!(device.cached_raw_brightness(), 80);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn coalesced_writes_only_write_the_latest_brightness() {
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 0, 100);
+        let mut calibright = builder(&fs)
+            .with_coalesced_writes(true)
+            .build()
+            .await
+            .unwrap();
+
+        // Like scrubbing a slider
+        for step in 1..=20 {
+            calibright.set_brightness(step as f64 / 20.0).await.unwrap();
+        }
+        // With the time paused, sleeping only moves the time on once the writer is idle
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let write_file = backlight_file(NAME, FILE_BRIGHTNESS_WRITE);
+        assert!(
+            fs.writes(&write_file) <= 2,
+            "{} writes",
+            fs.writes(&write_file)
+        );
+        assert_eq!(read_number(&fs.fs, &write_file), 100);
+        // The write is in the device owned by `calibright`, not just a copy
+        assert_eq!(calibright.get_brightness_cached().unwrap(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn coarsest_step_is_the_largest_of_all_devices() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, "coarse", 5, 10);
+        insert_backlight(&fs, "fine", 32768, 65536);
+        let calibright = calibright(&fs).await;
+        assert!((calibright.coarsest_step().unwrap() - 0.1).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn errors_name_the_device() {
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, NAME, 50, 100);
+        let mut calibright = builder(&fs)
+            .with_config(config("[global]\nddcci_read_base_ms = 1"))
+            .build()
+            .await
+            .unwrap();
+
+        fs.fail_reads(backlight_file(NAME, FILE_BRIGHTNESS), u32::MAX);
+        fs.fail_reads(backlight_file(NAME, FILE_BRIGHTNESS_WRITE), u32::MAX);
+        let err = calibright.get_brightness().await.unwrap_err();
+        assert!(err.to_string().contains(NAME), "{err}");
+
+        // The write file is missing
+        fs.fs.remove(backlight_file(NAME, FILE_BRIGHTNESS_WRITE));
+        let err = calibright.set_brightness(0.5).await.unwrap_err();
+        assert!(err.to_string().contains(NAME), "{err}");
+    }
+
+    #[tokio::test]
+    async fn device_groups_count_once_and_write_all_members() {
+        let fs = two_backlights();
+        insert_backlight(&fs, "c", 100, 100);
+        let mut calibright = builder(&fs)
+            .with_device_group(&["b", "a"])
+            .build()
+            .await
+            .unwrap();
+        // `a` is left out as `b` is counted for the group
+        assert!((calibright.get_brightness().await.unwrap() - 0.8).abs() < 1e-9);
+        assert!((calibright.get_brightness_cached().unwrap() - 0.8).abs() < 1e-9);
+
+        calibright.set_brightness(0.5).await.unwrap();
+        assert_eq!(written(&fs, &["a", "b", "c"]), [50, 50, 50]);
+    }
+
+    #[test]
+    fn acpi_video_and_native_backlights_are_duplicates() {
+        let names = |names: &[(Subsystem, &str)]| -> Vec<(Subsystem, String)> {
+            names
+                .iter()
+                .map(|&(subsystem, name)| (subsystem, name.to_string()))
+                .collect()
+        };
+        let device_names = names(&[
+            (Subsystem::Backlight, "acpi_video0"),
+            (Subsystem::Backlight, "intel_backlight"),
+            (Subsystem::Backlight, "ddcci5"),
+        ]);
+        assert_eq!(
+            duplicate_panel_controls(&device_names),
+            Some((vec!["acpi_video0"], vec!["intel_backlight"]))
+        );
+
+        let device_names = names(&[
+            (Subsystem::Backlight, "intel_backlight"),
+            (Subsystem::Backlight, "ddcci5"),
+            (Subsystem::Leds, "input0::capslock"),
+        ]);
+        assert_eq!(duplicate_panel_controls(&device_names), None);
+        let device_names = names(&[(Subsystem::Backlight, "acpi_video0")]);
+        assert_eq!(duplicate_panel_controls(&device_names), None);
+    }
+
+    #[tokio::test]
+    async fn entries_without_brightness_files_are_skipped() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, "a", 20, 100);
+        // E.g. a placeholder left behind by a driver
+        fs.insert(Path::new(DEVICES_PATH).join("bogus").join("uevent"), "");
+
+        let device_names = scan_device_names(
+            &Regex::new(".").unwrap(),
+            &[Subsystem::Backlight],
+            &config(""),
+            &device_options(&fs),
+        )
+        .await
+        .unwrap();
+        assert_eq!(device_names, [(Subsystem::Backlight, "a".to_string())]);
+        assert_eq!(calibright(&fs).await.matched_device_names(), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn cycling_past_the_max_wraps_to_the_min() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 85, 100);
+        let read_file = backlight_file(NAME, FILE_BRIGHTNESS);
+        let mut calibright = calibright(&fs).await;
+
+        // The max is set before wrapping
+        calibright.cycle_brightness(0.2, 0.1, 1.0).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [100]);
+        fs.insert(&read_file, "100");
+        calibright.cycle_brightness(0.2, 0.1, 1.0).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [10]);
+        fs.insert(&read_file, "10");
+        calibright.cycle_brightness(0.2, 0.1, 1.0).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [30]);
+
+        // A lower max wraps earlier
+        fs.insert(&read_file, "50");
+        calibright.cycle_brightness(0.2, 0.1, 0.5).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [10]);
+        assert!(calibright.cycle_brightness(0.2, 0.6, 0.5).await.is_err());
+    }
+
+    #[cfg(feature = "watch")]
+    fn changed(device: &str) -> ChangeEvent {
+        ChangeEvent::BrightnessChanged {
+            device: device.into(),
+        }
+    }
+
+    #[cfg(feature = "watch")]
+    fn modify_event(path: PathBuf) -> notify::Event {
+        use notify::event::{DataChange, ModifyKind};
+
+        notify::Event::new(notify::EventKind::Modify(ModifyKind::Data(
+            DataChange::Content,
+        )))
+        .add_path(path)
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test(start_paused = true)]
+    async fn duplicate_events_report_one_change() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        let poll_interval = Duration::from_millis(50);
+        let mut calibright = builder(&fs)
+            .with_poll_interval(poll_interval)
+            .build()
+            .await
+            .unwrap();
+        // Changes right after the device was found or set are taken as our own
+        tokio::time::advance(poll_interval * 2).await;
+
+        let read_file = backlight_file(NAME, FILE_BRIGHTNESS);
+        fs.insert(&read_file, "80");
+        // Both the poll and the inotify watcher report the change
+        let event = modify_event(read_file);
+        assert_eq!(
+            calibright.handle_event(&event).await.unwrap(),
+            [changed(NAME)]
+        );
+        assert_eq!(calibright.handle_event(&event).await.unwrap(), []);
+        assert_eq!(calibright.get_brightness_cached().unwrap(), 0.8);
+
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "30");
+        assert_eq!(
+            calibright.handle_event(&event).await.unwrap(),
+            [changed(NAME)]
+        );
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test(start_paused = true)]
+    async fn own_writes_to_amdgpu_read_file_are_not_changes() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, "amdgpu_bl0", 50, 100);
+        let poll_interval = Duration::from_millis(50);
+        let mut calibright = builder(&fs)
+            .with_poll_interval(poll_interval)
+            .build()
+            .await
+            .unwrap();
+        tokio::time::advance(poll_interval * 2).await;
+
+        // amdgpu is read from and written to the same file
+        let brightness_file = backlight_file("amdgpu_bl0", FILE_BRIGHTNESS_AMD);
+        let event = modify_event(brightness_file.clone());
+        calibright.set_brightness(0.3).await.unwrap();
+        assert_eq!(read_number(&fs, &brightness_file), 30);
+        assert_eq!(calibright.handle_event(&event).await.unwrap(), []);
+
+        tokio::time::advance(poll_interval * 2).await;
+        fs.insert(&brightness_file, "70");
+        assert_eq!(
+            calibright.handle_event(&event).await.unwrap(),
+            [changed("amdgpu_bl0")]
+        );
+        assert_eq!(calibright.get_brightness_cached().unwrap(), 0.7);
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test(start_paused = true)]
+    async fn firmware_changes_are_reported() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        let hw_changed_file = backlight_file(NAME, FILE_BRIGHTNESS_HW_CHANGED);
+        fs.insert(&hw_changed_file, "50");
+        let poll_interval = Duration::from_millis(50);
+        let mut calibright = builder(&fs)
+            .with_poll_interval(poll_interval)
+            .build()
+            .await
+            .unwrap();
+        tokio::time::advance(poll_interval * 2).await;
+
+        // A brightness key handled by the firmware, without a set_brightness
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "40");
+        fs.insert(&hw_changed_file, "40");
+        let event = modify_event(hw_changed_file);
+        assert_eq!(
+            calibright.handle_event(&event).await.unwrap(),
+            [changed(NAME)]
+        );
+        assert_eq!(calibright.get_brightness_cached().unwrap(), 0.4);
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test(start_paused = true)]
+    async fn next_returns_one_change_per_device() {
+        let fs = two_backlights();
+        let poll_interval = Duration::from_millis(50);
+        let mut calibright = builder(&fs)
+            .with_poll_interval(poll_interval)
+            .build()
+            .await
+            .unwrap();
+        tokio::time::advance(poll_interval * 2).await;
+
+        fs.insert(backlight_file("a", FILE_BRIGHTNESS), "30");
+        fs.insert(backlight_file("b", FILE_BRIGHTNESS), "70");
+        let event = modify_event(backlight_file("a", FILE_BRIGHTNESS))
+            .add_path(backlight_file("b", FILE_BRIGHTNESS));
+        calibright.tx.send(Ok(event)).await.unwrap();
+        assert_eq!(calibright.next().await.unwrap(), changed("a"));
+        assert_eq!(calibright.next().await.unwrap(), changed("b"));
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test(start_paused = true)]
+    async fn dropping_next_while_adding_devices_loses_no_change() {
+        use notify::event::CreateKind;
+
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, "a", 20, 100);
+        let mut calibright = calibright(&fs).await;
+        insert_backlight(&fs.fs, "b", 40, 100);
+        insert_backlight(&fs.fs, "c", 60, 100);
+        let slow_file = backlight_file("c", FILE_MAX_BRIGHTNESS);
+        fs.delay_reads(&slow_file, Duration::from_secs(1));
+        let event = notify::Event::new(notify::EventKind::Create(CreateKind::Folder))
+            .add_path(Path::new(DEVICES_PATH).join("b"))
+            .add_path(Path::new(DEVICES_PATH).join("c"));
+        calibright.tx.send(Ok(event)).await.unwrap();
+
+        // Dropped after "b" was added, while reading "c"
+        let next = tokio::time::timeout(Duration::from_millis(50), calibright.next()).await;
+        assert!(next.is_err());
+        assert_eq!(calibright.devices.len(), 2);
+
+        fs.delay_reads(&slow_file, Duration::ZERO);
+        let added = |device: &str| ChangeEvent::DeviceAdded {
+            device: device.into(),
+        };
+        assert_eq!(calibright.next().await.unwrap(), added("b"));
+        assert_eq!(calibright.next().await.unwrap(), added("c"));
+        assert_eq!(calibright.devices.len(), 3);
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test(start_paused = true)]
+    async fn adjusting_after_an_external_change_starts_from_the_new_brightness() {
+        let fs = Arc::new(MemoryFs::new());
+        insert_backlight(&fs, NAME, 50, 100);
+        let poll_interval = Duration::from_millis(50);
+        let mut calibright = builder(&fs)
+            .with_poll_interval(poll_interval)
+            .build()
+            .await
+            .unwrap();
+        tokio::time::advance(poll_interval * 2).await;
+
+        fs.insert(backlight_file(NAME, FILE_BRIGHTNESS), "20");
+        let event = modify_event(backlight_file(NAME, FILE_BRIGHTNESS));
+        calibright.tx.send(Ok(event)).await.unwrap();
+        assert_eq!(calibright.next().await.unwrap(), changed(NAME));
+        assert_eq!(calibright.cached_raw_brightness(), [(OsStr::new(NAME), 20)]);
+
+        calibright.adjust_brightness(0.1).await.unwrap();
+        assert_eq!(written(&fs, &[NAME]), [30]);
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test(start_paused = true)]
+    async fn on_change_reports_changes_of_the_same_devices() {
+        let fs = two_backlights();
+        let poll_interval = Duration::from_millis(50);
+        let (changes_tx, mut changes) = tokio::sync::mpsc::unbounded_channel();
+        let mut calibright = builder(&fs)
+            .with_poll_interval(poll_interval)
+            .with_on_change(move |change| {
+                let _ = changes_tx.send(change);
+            })
+            .build()
+            .await
+            .unwrap();
+
+        // Our own write isn't reported
+        calibright.set_brightness(0.9).await.unwrap();
+        fs.insert(backlight_file("a", FILE_BRIGHTNESS), "90");
+        let event = modify_event(backlight_file("a", FILE_BRIGHTNESS));
+        calibright.tx.send(Ok(event)).await.unwrap();
+        // With the time paused, sleeping only moves the time on once the observer handled the
+        // event
+        tokio::time::sleep(poll_interval * 2).await;
+
+        fs.insert(backlight_file("b", FILE_BRIGHTNESS), "30");
+        let event = modify_event(backlight_file("b", FILE_BRIGHTNESS));
+        calibright.tx.send(Ok(event)).await.unwrap();
+        assert_eq!(changes.recv().await.unwrap(), changed("b"));
+        assert!(changes.try_recv().is_err());
+        // The observed brightness is cached by the instance itself
+        assert!((calibright.get_brightness_cached().unwrap() - 0.6).abs() < 1e-9);
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test]
+    async fn next_after_shutdown_is_an_error() {
+        let fs = two_backlights();
+        let mut calibright = calibright(&fs).await;
+        let event = modify_event(backlight_file("a", FILE_BRIGHTNESS));
+        calibright.tx.send(Ok(event)).await.unwrap();
+
+        calibright.shutdown().unwrap();
+        assert!(calibright.next().await.is_err());
+        // Sends of the watchers fail from now on, which they ignore
+        assert!(calibright
+            .tx
+            .try_send(Ok(notify::Event::default()))
+            .is_err());
+        assert!((calibright.get_brightness().await.unwrap() - 0.4).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,52 @@
+use crate::device::Device;
+
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Instant, MissedTickBehavior};
+
+make_log_macro!(debug, "calibright_refresh");
+
+/// Periodically reads the brightness of a set of devices on a separate task, so that the cached
+/// brightness can be used without blocking on slow (e.g. DDC) reads.
+///
+/// The devices are clones sharing their cached brightness with the devices of the
+/// [`crate::Calibright`], so the readings show up there directly.
+///
+/// The task is stopped when this is dropped.
+pub(crate) struct BackgroundRefresh {
+    task: JoinHandle<()>,
+}
+
+impl BackgroundRefresh {
+    pub fn spawn(devices: Vec<Device>, refresh_interval: Duration) -> Self {
+        let task = tokio::spawn(refresh(devices, refresh_interval));
+
+        Self { task }
+    }
+}
+
+impl Drop for BackgroundRefresh {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn refresh(devices: Vec<Device>, refresh_interval: Duration) {
+    let mut ticker = interval(refresh_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        join_all(devices.iter().map(|device| async move {
+            let read_at = Instant::now();
+            match device.peek_raw().await {
+                Ok(raw_brightness) => device.update_raw_brightness_read_at(raw_brightness, read_at),
+                Err(e) => {
+                    debug!("{e}");
+                }
+            }
+        }))
+        .await;
+    }
+}
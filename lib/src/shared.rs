@@ -0,0 +1,136 @@
+use crate::errors::*;
+use crate::Calibright;
+
+use std::sync::Arc;
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use tokio::sync::{Mutex, MutexGuard};
+
+// `SharedCalibright` can only be shared between tasks if `Calibright` can be sent between threads.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Calibright>();
+};
+
+/// A read of the brightness that several callers can wait for. The error is shared, so it is
+/// behind an `Arc`.
+type PendingRead = Shared<BoxFuture<'static, std::result::Result<f64, Arc<CalibrightError>>>>;
+
+/// A cloneable handle to a [`Calibright`] that can be shared between tasks.
+///
+/// All operations lock the underlying [`Calibright`], so concurrent calls are serialized rather
+/// than interleaved: a `set_brightness` from one task is never mixed with a `get_brightness` from
+/// another. Avoid holding the guard returned by [`SharedCalibright::lock`] across a long running
+/// `next()`, as that blocks every other handle until a change occurs.
+///
+/// Concurrent [`SharedCalibright::get_brightness`] calls are coalesced: callers made while a read
+/// is pending wait for that read and share its result instead of reading the devices again, which
+/// avoids redundant (and slow DDC/CI) reads when several consumers poll at once.
+#[derive(Clone)]
+pub struct SharedCalibright {
+    inner: Arc<Mutex<Calibright>>,
+    pending_read: Arc<std::sync::Mutex<Option<PendingRead>>>,
+}
+
+impl SharedCalibright {
+    /// Wrap a [`Calibright`] so it can be shared.
+    pub fn new(calibright: Calibright) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(calibright)),
+            pending_read: Arc::default(),
+        }
+    }
+
+    /// Lock the underlying [`Calibright`] for operations not covered by this wrapper.
+    pub async fn lock(&self) -> MutexGuard<'_, Calibright> {
+        // The brightness may be changed through the guard
+        self.forget_pending_read();
+        self.inner.lock().await
+    }
+
+    /// See [`Calibright::get_brightness`].
+    pub async fn get_brightness(&self) -> Result<f64> {
+        let read = {
+            let mut pending_read = self.pending_read.lock().unwrap();
+            match &*pending_read {
+                Some(read) if read.peek().is_none() => read.clone(),
+                _ => {
+                    let inner = self.inner.clone();
+                    let read =
+                        async move { inner.lock().await.get_brightness().await.map_err(Arc::new) }
+                            .boxed()
+                            .shared();
+                    *pending_read = Some(read.clone());
+                    read
+                }
+            }
+        };
+        let result = read.clone().await;
+        {
+            let mut pending_read = self.pending_read.lock().unwrap();
+            if pending_read
+                .as_ref()
+                .is_some_and(|pending| pending.ptr_eq(&read))
+            {
+                *pending_read = None;
+            }
+        }
+        // The last caller to get the error gets the original
+        drop(read);
+        result.map_err(|e| {
+            Arc::try_unwrap(e).unwrap_or_else(|e| CalibrightError::Other(e.to_string()))
+        })
+    }
+
+    /// See [`Calibright::set_brightness`].
+    pub async fn set_brightness(&self, brightness: f64) -> Result<()> {
+        self.forget_pending_read();
+        self.inner.lock().await.set_brightness(brightness).await
+    }
+
+    /// Don't let later calls share a pending read, as it may have been made before a change.
+    fn forget_pending_read(&self) {
+        *self.pending_read.lock().unwrap() = None;
+    }
+}
+
+impl From<Calibright> for SharedCalibright {
+    fn from(calibright: Calibright) -> Self {
+        Self::new(calibright)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::consts::*;
+    use crate::fs::tests::*;
+
+    #[tokio::test]
+    async fn concurrent_reads_share_one_read() {
+        let fs = Arc::new(FlakyFs::default());
+        insert_backlight(&fs.fs, "a", 20, 100);
+        let read_file = backlight_file("a", FILE_BRIGHTNESS);
+        let shared = SharedCalibright::new(calibright(&fs).await);
+        let reads = fs.reads(&read_file);
+
+        // Both reads are pending until the guard is dropped
+        let guard = shared.lock().await;
+        let (first, second, ()) = tokio::join!(
+            shared.get_brightness(),
+            shared.get_brightness(),
+            async move {
+                tokio::task::yield_now().await;
+                drop(guard);
+            }
+        );
+        assert_eq!(first.unwrap(), 0.2);
+        assert_eq!(second.unwrap(), 0.2);
+        assert_eq!(fs.reads(&read_file) - reads, 1);
+
+        // A read after the shared one has finished reads the devices again
+        shared.get_brightness().await.unwrap();
+        assert_eq!(fs.reads(&read_file) - reads, 2);
+    }
+}
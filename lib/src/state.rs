@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::*;
+use crate::util::*;
+
+make_log_macro!(debug, "calibright_state");
+
+/// The last raw brightness of each device, persisted with
+/// [`crate::CalibrightBuilder::with_state_file`]:
+///
+/// ```toml
+/// [devices]
+/// intel_backlight = 12000
+/// ddcci5 = 40
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct State {
+    pub devices: BTreeMap<String, u32>,
+}
+
+impl State {
+    /// Read the state file. A missing or corrupt state file is ignored, as there is nothing to
+    /// restore then.
+    pub async fn load(path: &Path) -> Option<Self> {
+        let contents = match read_file(path).await {
+            Ok(contents) => contents,
+            Err(e) => {
+                debug!("Ignoring state file {}: {e}", path.display());
+                return None;
+            }
+        };
+        match deserialize_toml_str(&contents) {
+            Ok(state) => Some(state),
+            Err(message) => {
+                debug!("Ignoring state file {}: {message}", path.display());
+                None
+            }
+        }
+    }
+
+    /// Write the state file, replacing it atomically so that an interrupted write doesn't corrupt
+    /// it.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).map_err(|e| CalibrightError::Other(e.to_string()))?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, contents).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use notify::event::{CreateKind, DataChange, EventKind, ModifyKind, RemoveKind};
+use notify::{Error, Event, Result};
+use tokio::sync::mpsc::Sender;
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::consts::{FILE_BRIGHTNESS, FILE_BRIGHTNESS_LEDS};
+
+make_log_macro!(debug, "calibright_uevent");
+
+/// Multicast group the kernel sends uevents to.
+const UEVENT_GROUP_KERNEL: u32 = 1;
+
+/// How often to check whether the receiver went away.
+const POLL_TIMEOUT_MS: i32 = 500;
+
+/// Translates kernel uevents (the same events udev consumes) for the watched device classes into
+/// [`notify`] create/remove events, so device hotplug is picked up without polling the sysfs
+/// directories. Change events (e.g. backlights changed by firmware handled brightness keys) are
+/// translated into modify events of the brightness files.
+pub struct UeventWatcher {
+    _thread: thread::JoinHandle<()>,
+}
+
+impl UeventWatcher {
+    pub fn new<P: AsRef<Path>>(paths: &[P], tx: Sender<Result<Event>>) -> Result<Self> {
+        let socket = open_uevent_socket().map_err(Error::io)?;
+        // Map the subsystem name (e.g. "backlight") to the sysfs class directory
+        let classes: Vec<(String, PathBuf)> = paths
+            .iter()
+            .filter_map(|path| {
+                let path = path.as_ref();
+                path.file_name()
+                    .map(|name| (name.to_string_lossy().to_string(), path.to_path_buf()))
+            })
+            .collect();
+
+        let thread = thread::Builder::new()
+            .name("calibright-uevent".into())
+            .spawn(move || listen(socket, classes, tx))
+            .map_err(Error::io)?;
+
+        Ok(Self { _thread: thread })
+    }
+}
+
+fn open_uevent_socket() -> io::Result<OwnedFd> {
+    // SAFETY: plain libc calls, the returned fd is checked before being wrapped in an `OwnedFd`
+    unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        );
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = OwnedFd::from_raw_fd(fd);
+
+        let mut addr: libc::sockaddr_nl = std::mem::zeroed();
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = UEVENT_GROUP_KERNEL;
+        if libc::bind(
+            socket.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        ) < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(socket)
+    }
+}
+
+fn listen(socket: OwnedFd, classes: Vec<(String, PathBuf)>, tx: Sender<Result<Event>>) {
+    let mut buf = vec![0u8; 8192];
+    while !tx.is_closed() {
+        let mut pollfd = libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `pollfd` is a valid pointer to a single `pollfd`
+        let ready = unsafe { libc::poll(&mut pollfd, 1, POLL_TIMEOUT_MS) };
+        if ready <= 0 {
+            continue;
+        }
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes
+        let len = unsafe {
+            libc::recv(
+                socket.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if len <= 0 {
+            continue;
+        }
+        if let Some(event) = parse_uevent(&buf[..len as usize], &classes) {
+            debug!("{:?}", event);
+            if tx.blocking_send(Ok(event)).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// A uevent is a `\0` separated list, starting with `ACTION@DEVPATH` followed by `KEY=VALUE` pairs.
+fn parse_uevent(msg: &[u8], classes: &[(String, PathBuf)]) -> Option<Event> {
+    let mut action = None;
+    let mut subsystem = None;
+    let mut devpath = None;
+    for field in msg.split(|&b| b == 0).skip(1) {
+        let field = std::str::from_utf8(field).ok()?;
+        if let Some((key, value)) = field.split_once('=') {
+            match key {
+                "ACTION" => action = Some(value),
+                "SUBSYSTEM" => subsystem = Some(value),
+                "DEVPATH" => devpath = Some(value),
+                _ => (),
+            }
+        }
+    }
+
+    let subsystem = subsystem?;
+    let (_, class_path) = classes.iter().find(|(name, _)| name == subsystem)?;
+    let device_path = class_path.join(Path::new(devpath?).file_name()?);
+
+    match action? {
+        "add" => Some(Event::new(EventKind::Create(CreateKind::Folder)).add_path(device_path)),
+        "remove" => Some(Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(device_path)),
+        // Only one of these is the file the device is read from, the other is ignored
+        "change" => Some(
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any)))
+                .add_path(device_path.join(FILE_BRIGHTNESS))
+                .add_path(device_path.join(FILE_BRIGHTNESS_LEDS)),
+        ),
+        _ => None,
+    }
+}
@@ -1,10 +1,12 @@
 use std::path::Path;
 
-use futures_util::future::join_all;
+use futures_util::future::{join_all, JoinAll, Map};
+use futures_util::FutureExt;
 use serde::de::DeserializeOwned;
 use std::future::Future;
 use tokio::io::AsyncReadExt;
 
+use crate::consts::BRIGHTNESS_SNAP_EPSILON;
 use crate::errors::*;
 
 macro_rules! make_log_macro {
@@ -30,7 +32,34 @@ where
 
     let contents = read_file(path).await?;
 
-    toml::from_str(&contents).map_err(|err| {
+    deserialize_toml_str(&contents).map_err(|message| CalibrightError::Config {
+        path: Some(path.to_path_buf()),
+        message,
+    })
+}
+
+#[cfg(feature = "json")]
+pub async fn deserialize_json_file<T, P>(path: P) -> Result<T>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    let contents = read_file(path).await?;
+
+    crate::json::deserialize_json_str(&contents).map_err(|message| CalibrightError::Config {
+        path: Some(path.to_path_buf()),
+        message,
+    })
+}
+
+/// Deserialize TOML, returning an error message with the line of the error on failure.
+pub fn deserialize_toml_str<T>(contents: &str) -> std::result::Result<T, String>
+where
+    T: DeserializeOwned,
+{
+    toml::from_str(contents).map_err(|err| {
         #[allow(deprecated)]
         let location_msg = err
             .span()
@@ -39,23 +68,44 @@ where
                     .iter()
                     .filter(|b| **b == b'\n')
                     .count();
-                format!(" at line {line}")
+                format!("line {line}: ")
             })
             .unwrap_or_default();
-        CalibrightError::Other(format!(
-            "Failed to deserialize TOML file {}{}: {}",
-            path.display(),
-            location_msg,
-            err.message()
-        ))
+        format!("{}{}", location_msg, err.message())
     })
 }
 
+/// Read a file, with trailing whitespace (e.g. the newline of sysfs attributes) removed.
 pub async fn read_file(path: impl AsRef<Path>) -> std::io::Result<String> {
+    Ok(read_file_raw(path).await?.trim_end().to_string())
+}
+
+/// Read a file exactly as it is, for content where whitespace matters. Parsers such as
+/// [`parse_u32`] do their own trimming.
+pub async fn read_file_raw(path: impl AsRef<Path>) -> std::io::Result<String> {
     let mut file = tokio::fs::File::open(path).await?;
     let mut content = String::new();
     file.read_to_string(&mut content).await?;
-    Ok(content.trim_end().to_string())
+    Ok(content)
+}
+
+/// Parse the leading integer of a sysfs value, ignoring surrounding whitespace and anything
+/// after the digits (e.g. `" 123 "`, `"123\n"` and `"123.0"` all parse as `123`).
+pub fn parse_u32(content: &[u8]) -> Result<u32> {
+    let content = content.trim_ascii();
+    let digits_len = content
+        .iter()
+        .position(|b| !b.is_ascii_digit())
+        .unwrap_or(content.len());
+    std::str::from_utf8(&content[..digits_len])
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| {
+            CalibrightError::Other(format!(
+                "Failed to parse {:?} as a brightness value",
+                String::from_utf8_lossy(content)
+            ))
+        })
 }
 
 /// Scale a number from 0.0-1.0 to an arbitrary scale
@@ -67,21 +117,105 @@ pub fn scale_to_clamped_relative(absolute_value: f64, low: f64, high: f64) -> Re
     }
 }
 
-// Scale a number from an arbitrary scale to 0.0-1.0
-pub fn scale_to_clamped_absolute(relative_value: f64, low: f64, high: f64) -> Result<f64> {
+// Scale a number from an arbitrary scale to 0.0-1.0, snapping results within `snap` of either
+// end to exactly 0.0 or 1.0
+pub fn scale_to_clamped_absolute(
+    relative_value: f64,
+    low: f64,
+    high: f64,
+    snap: f64,
+) -> Result<f64> {
     if low > high {
         Err(CalibrightError::InvalidScaleParameters { low, high })
     } else {
-        Ok((relative_value.clamp(low, high) - low) / (high - low))
+        let absolute_value = (relative_value.clamp(low, high) - low) / (high - low);
+        // Float rounding can leave the bounds slightly off, e.g. `0.9999...` at the hardware max
+        let snap = snap.max(BRIGHTNESS_SNAP_EPSILON);
+        Ok(if absolute_value < snap {
+            0.0
+        } else if absolute_value > 1.0 - snap {
+            1.0
+        } else {
+            absolute_value
+        })
+    }
+}
+
+/// Check that `points` define a strictly increasing curve from `x = 0.0` to `x = 1.0` with values
+/// in range 0.0 to 1.0, so that it can be interpolated and inverted.
+pub fn check_curve_points(points: &[[f64; 2]]) -> std::result::Result<(), String> {
+    if points.len() < 2 {
+        return Err("at least 2 points are required".into());
+    }
+    if points
+        .iter()
+        .flatten()
+        .any(|val| !(0.0..=1.0).contains(val))
+    {
+        return Err("coordinates must be in the range of 0.0 to 1.0".into());
+    }
+    if points[0][0] != 0.0 || points[points.len() - 1][0] != 1.0 {
+        return Err("the first point must be at 0.0 and the last point at 1.0".into());
+    }
+    if points
+        .windows(2)
+        .any(|pair| pair[0][0] >= pair[1][0] || pair[0][1] >= pair[1][1])
+    {
+        return Err("points must be strictly increasing".into());
+    }
+    Ok(())
+}
+
+/// Map `x` (0.0 to 1.0) through the piecewise-linear curve defined by `points`, which have to
+/// pass [`check_curve_points`].
+pub fn interpolate_curve(points: &[[f64; 2]], x: f64) -> f64 {
+    interpolate(points.iter().map(|&[x, y]| (x, y)), x)
+}
+
+/// The inverse of [`interpolate_curve`].
+pub fn interpolate_curve_inverse(points: &[[f64; 2]], y: f64) -> f64 {
+    interpolate(points.iter().map(|&[x, y]| (y, x)), y)
+}
+
+fn interpolate(points: impl Iterator<Item = (f64, f64)> + Clone, x: f64) -> f64 {
+    let mut previous = None;
+    for (x1, y1) in points {
+        match previous {
+            Some((x0, y0)) if x <= x1 => return y0 + (x - x0) * (y1 - y0) / (x1 - x0),
+            None if x <= x1 => return y1,
+            _ => previous = Some((x1, y1)),
+        }
     }
+    previous.map_or(x, |(_, y)| y)
 }
 
-pub async fn join_all_accept_single_ok<I, T>(iter: I) -> Result<Vec<T>>
+/// Average of `(value, weight)` pairs. Values with a weight of `0.0` are ignored.
+pub fn weighted_mean(values: &[(f64, f64)]) -> Result<f64> {
+    let total_weight: f64 = values.iter().map(|(_, weight)| weight).sum();
+    if total_weight <= 0.0 {
+        return Err(CalibrightError::NoDevices);
+    }
+    Ok(values
+        .iter()
+        .map(|(value, weight)| value * weight)
+        .sum::<f64>()
+        / total_weight)
+}
+
+/// Future returned by [`join_all_accept_single_ok`].
+pub type JoinAllAcceptSingleOk<F, T> = Map<JoinAll<F>, fn(Vec<Result<T>>) -> Result<Vec<T>>>;
+
+/// The returned future doesn't depend on the type of the iterator (and its closures), which would
+/// otherwise keep futures awaiting it from being `Send`.
+pub fn join_all_accept_single_ok<I, F, T>(iter: I) -> JoinAllAcceptSingleOk<F, T>
 where
-    I: IntoIterator,
-    I::Item: Future<Output = Result<T>>,
+    I: IntoIterator<Item = F>,
+    F: Future<Output = Result<T>>,
 {
-    let all_results = join_all(iter).await;
+    join_all(iter).map(accept_single_ok)
+}
+
+fn accept_single_ok<T>(all_results: Vec<Result<T>>) -> Result<Vec<T>> {
     let mut results: Vec<T> = Vec::new();
     let mut error = CalibrightError::NoDevices;
     for result in all_results {
@@ -96,3 +230,100 @@ where
         Ok(results)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_u32_ignores_whitespace_and_fractions() {
+        assert_eq!(parse_u32(b" 123 ").unwrap(), 123);
+        assert_eq!(parse_u32(b"123\n").unwrap(), 123);
+        assert_eq!(parse_u32(b"123.0").unwrap(), 123);
+    }
+
+    #[test]
+    fn parse_u32_reports_the_content() {
+        let CalibrightError::Other(message) = parse_u32(b" max\n").unwrap_err() else {
+            panic!("expected CalibrightError::Other");
+        };
+        assert_eq!(message, r#"Failed to parse "max" as a brightness value"#);
+    }
+
+    #[test]
+    fn scale_to_clamped_absolute_is_exact_at_max_brightness() {
+        for max_brightness in [7_u32, 255, 937, 120_000] {
+            let raw_brightness = max_brightness;
+            let ratio = raw_brightness as f64 / max_brightness as f64;
+            assert_eq!(
+                scale_to_clamped_absolute(ratio, 0.0, 1.0, 0.0).unwrap(),
+                1.0
+            );
+        }
+        // Rounding just below the bounds snaps to them
+        assert_eq!(
+            scale_to_clamped_absolute(1.0 - 1e-12, 0.0, 1.0, 0.0).unwrap(),
+            1.0
+        );
+        assert_eq!(
+            scale_to_clamped_absolute(0.1 + 1e-12, 0.1, 0.9, 0.0).unwrap(),
+            0.0
+        );
+        assert_eq!(scale_to_clamped_absolute(0.5, 0.0, 1.0, 0.0).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn curve_interpolation_round_trips() {
+        let curves: [&[[f64; 2]]; 3] = [
+            &[[0.0, 0.0], [1.0, 1.0]],
+            &[[0.0, 0.0], [0.5, 0.2], [1.0, 1.0]],
+            &[[0.0, 0.1], [0.25, 0.15], [0.75, 0.6], [1.0, 0.9]],
+        ];
+        for points in curves {
+            check_curve_points(points).unwrap();
+            for step in 0..=100 {
+                let x = step as f64 / 100.0;
+                let y = interpolate_curve(points, x);
+                assert!((interpolate_curve_inverse(points, y) - x).abs() < 1e-9);
+            }
+            for &[x, y] in points {
+                assert_eq!(interpolate_curve(points, x), y);
+                assert_eq!(interpolate_curve_inverse(points, y), x);
+            }
+        }
+        // Linear between the points
+        let points = [[0.0, 0.0], [0.5, 0.2], [1.0, 1.0]];
+        assert!((interpolate_curve(&points, 0.25) - 0.1).abs() < 1e-9);
+        assert!((interpolate_curve(&points, 0.75) - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn check_curve_points_rejects_unusable_curves() {
+        let invalid: [&[[f64; 2]]; 6] = [
+            &[[0.0, 0.0]],
+            &[[0.0, 0.0], [1.0, 1.5]],
+            &[[0.1, 0.0], [1.0, 1.0]],
+            &[[0.0, 0.0], [0.9, 1.0]],
+            &[[0.0, 0.0], [0.5, 0.5], [0.5, 0.6], [1.0, 1.0]],
+            &[[0.0, 0.0], [0.5, 0.6], [0.7, 0.6], [1.0, 1.0]],
+        ];
+        for points in invalid {
+            assert!(check_curve_points(points).is_err(), "{points:?}");
+        }
+    }
+
+    #[test]
+    fn scale_to_clamped_absolute_snaps_near_the_calibration_bounds() {
+        let scale = |value| scale_to_clamped_absolute(value, 0.2, 0.8, 0.01).unwrap();
+        // Within the snap distance (1% of the window) of the bounds
+        assert_eq!(scale(0.2), 0.0);
+        assert_eq!(scale(0.2 + 0.005), 0.0);
+        assert_eq!(scale(0.2 - 0.005), 0.0);
+        assert_eq!(scale(0.8), 1.0);
+        assert_eq!(scale(0.8 - 0.005), 1.0);
+        assert_eq!(scale(0.8 + 0.005), 1.0);
+        // Just outside of it
+        assert!((scale(0.2 + 0.012) - 0.02).abs() < 1e-9);
+        assert!((scale(0.8 - 0.012) - 0.98).abs() < 1e-9);
+    }
+}
@@ -1,37 +1,220 @@
-use notify::{Config, Event, RecursiveMode, Result};
-pub(crate) use notify::{INotifyWatcher, PollWatcher, Watcher};
+#[cfg(not(feature = "udev"))]
+use notify::PollWatcher;
+use notify::{Config, Event, Result};
+pub(crate) use notify::{INotifyWatcher, Watcher};
 use tokio::sync::mpsc::channel;
-pub(crate) use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Receiver, Sender};
 
+use crate::device::Device;
+use crate::{brightness_changes, device_matches, subsystem_entries, ChangeEvent};
+
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 pub use std::time::Duration;
 
+pub(crate) type EventSender = Sender<Result<Event>>;
+pub(crate) type EventReceiver = Receiver<Result<Event>>;
+
+/// Observes the devices as they come/go
+#[cfg(not(feature = "udev"))]
+pub(crate) type DeviceWatcher = PollWatcher;
+#[cfg(feature = "udev")]
+pub(crate) type DeviceWatcher = crate::uevent::UeventWatcher;
+
 pub fn pseudo_fs_watcher<P: AsRef<Path>>(
-    path: P,
+    paths: &[P],
     poll_interval: Duration,
-) -> Result<(PollWatcher, INotifyWatcher, Receiver<Result<Event>>)> {
+    hotplug: bool,
+) -> Result<(
+    Option<DeviceWatcher>,
+    INotifyWatcher,
+    EventSender,
+    EventReceiver,
+)> {
     let (tx, rx) = channel(1);
 
-    let config = Config::default()
-        .with_compare_contents(true) // crucial part for pseudo filesystems
-        .with_poll_interval(poll_interval);
+    let device_watcher = if hotplug {
+        Some(device_watcher(paths, poll_interval, tx.clone())?)
+    } else {
+        None
+    };
 
-    // PollWatcher is used to observe the devices as they come/go
-    let poll_tx = tx.clone();
-    let mut poll_watcher = PollWatcher::new(
-        move |res: notify::Result<Event>| poll_tx.blocking_send(res).unwrap(),
-        config,
+    // INotifyWatcher watches the contents of the files. Sending fails once the receiver is
+    // closed by `Calibright::shutdown` or dropped, then the event is of no interest.
+    let inotify_tx = tx.clone();
+    let inotify_watcher = INotifyWatcher::new(
+        move |res: notify::Result<Event>| {
+            let _ = inotify_tx.blocking_send(res);
+        },
+        watcher_config(poll_interval),
     )?;
 
-    // INotifyWatcher watches the contents of the files
-    let inotify_watcher = INotifyWatcher::new(
-        move |res: notify::Result<Event>| tx.blocking_send(res).unwrap(),
-        config,
+    Ok((device_watcher, inotify_watcher, tx, rx))
+}
+
+/// Create a [`DeviceWatcher`] for the given paths, sending its events to `tx`.
+pub fn device_watcher<P: AsRef<Path>>(
+    paths: &[P],
+    poll_interval: Duration,
+    tx: EventSender,
+) -> Result<DeviceWatcher> {
+    #[cfg(not(feature = "udev"))]
+    {
+        // PollWatcher is used to observe the devices as they come/go
+        let mut poll_watcher = PollWatcher::new(
+            move |res: notify::Result<Event>| {
+                let _ = tx.blocking_send(res);
+            },
+            watcher_config(poll_interval),
+        )?;
+
+        // Add the paths to be watched. The files and directories directly in those paths
+        // will be monitored for changes.
+        for path in paths {
+            poll_watcher.watch(path.as_ref(), notify::RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(poll_watcher)
+    }
+
+    // Kernel uevents are used to observe the devices as they come/go
+    #[cfg(feature = "udev")]
+    {
+        let _ = poll_interval;
+        crate::uevent::UeventWatcher::new(paths, tx)
+    }
+}
+
+fn watcher_config(poll_interval: Duration) -> Config {
+    Config::default()
+        .with_compare_contents(true) // crucial part for pseudo filesystems
+        .with_poll_interval(poll_interval)
+}
+
+/// Watch the files of `device` that change when its brightness changes.
+pub fn watch_device(inotify_watcher: &mut INotifyWatcher, device: &Device) -> Result<()> {
+    if !device.is_watchable() {
+        return Ok(());
+    }
+    inotify_watcher.watch(
+        &device.read_brightness_file,
+        notify::RecursiveMode::NonRecursive,
     )?;
+    if let Some(hw_changed_file) = &device.hw_changed_file {
+        inotify_watcher.watch(hw_changed_file, notify::RecursiveMode::NonRecursive)?;
+    }
+    Ok(())
+}
+
+/// Stop watching the files watched by [`watch_device`].
+pub fn unwatch_device(inotify_watcher: &mut INotifyWatcher, device: &Device) -> Result<()> {
+    if !device.is_watchable() {
+        return Ok(());
+    }
+    inotify_watcher.unwatch(&device.read_brightness_file)?;
+    if let Some(hw_changed_file) = &device.hw_changed_file {
+        inotify_watcher.unwatch(hw_changed_file)?;
+    }
+    Ok(())
+}
+
+/// Callback registered with [`crate::CalibrightBuilder::with_on_change`].
+pub(crate) type ChangeCallback = Box<dyn Fn(ChangeEvent) + Send + 'static>;
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    poll_watcher.watch(path.as_ref(), RecursiveMode::NonRecursive)?;
+/// Receives the events of the watchers of a [`crate::Calibright`] on a task, calling the
+/// callback with each change before passing the event on to [`crate::Calibright::next`].
+///
+/// The devices are clones sharing their state with the devices of the [`crate::Calibright`], so
+/// its own writes aren't reported and a change reported here isn't returned by `next` again.
+///
+/// The task is stopped when this is dropped.
+pub(crate) struct ChangeObserver {
+    devices: Arc<Mutex<HashMap<OsString, Device>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ChangeObserver {
+    /// Take over receiving the events of `calibright`, which gets the events passed on instead.
+    pub fn spawn(calibright: &mut crate::Calibright, callback: ChangeCallback) -> Self {
+        // Events are dropped rather than waiting for `next`, as it may never be called
+        let (forward, forwarded) = channel(16);
+        let mut rx = std::mem::replace(&mut calibright.rx, forwarded);
+        let devices = Arc::new(Mutex::new(calibright.devices.clone()));
+        let device_regex = calibright.device_regex.clone();
+        let subsystems = calibright.subsystems.clone();
+        let read_file = calibright.device_options.read_file.clone();
+        let fs = calibright.device_options.fs.clone();
+        let poll_interval = calibright.poll_interval;
+
+        let observed = devices.clone();
+        let task = tokio::spawn(async move {
+            while let Some(res) = rx.recv().await {
+                if let Ok(event) = &res {
+                    let mut devices = observed.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    let mut changes = Vec::new();
+                    let subsystem_entries = subsystem_entries(&subsystems, event);
+                    if event.kind.is_create() && !subsystem_entries.is_empty() {
+                        for (subsystem, path) in subsystem_entries {
+                            let Some(file_name) = path.file_name() else {
+                                continue;
+                            };
+                            if !devices.contains_key(file_name)
+                                && device_matches(
+                                    &device_regex,
+                                    subsystem,
+                                    &file_name.to_string_lossy(),
+                                    &*fs,
+                                )
+                                .await
+                            {
+                                changes.push(ChangeEvent::DeviceAdded {
+                                    device: file_name.to_owned(),
+                                });
+                            }
+                        }
+                    } else if event.kind.is_remove() && !subsystem_entries.is_empty() {
+                        for (_, path) in subsystem_entries {
+                            if let Some(file_name) =
+                                path.file_name().filter(|name| devices.contains_key(*name))
+                            {
+                                changes.push(ChangeEvent::DeviceRemoved {
+                                    device: file_name.to_owned(),
+                                });
+                            }
+                        }
+                    } else if event.kind.is_modify() {
+                        brightness_changes(
+                            event,
+                            &mut devices,
+                            &subsystems,
+                            read_file.as_deref(),
+                            poll_interval,
+                            &mut changes,
+                        )
+                        .await;
+                    }
+                    changes.into_iter().for_each(&callback);
+                }
+                if let Err(TrySendError::Full(_)) = forward.try_send(res) {
+                    log::debug!(target: "calibright", "event not passed on, next isn't called");
+                }
+            }
+        });
+
+        Self { devices, task }
+    }
+
+    /// Observe `devices` from now on, after devices were added or removed.
+    pub fn set_devices(&self, devices: HashMap<OsString, Device>) {
+        *self.devices.lock().unwrap_or_else(|e| e.into_inner()) = devices;
+    }
+}
 
-    Ok((poll_watcher, inotify_watcher, rx))
+impl Drop for ChangeObserver {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
 }